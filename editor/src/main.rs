@@ -0,0 +1,548 @@
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use engine::tiles::*;
+use engine::types::*;
+
+// Map view is the top 22x19 tiles, the rest of the screen is the palette
+const VIEW_W: i32 = 22;
+const VIEW_H: i32 = 19;
+const PALETTE_Y: i32 = VIEW_H * TILE_SZ;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Tool {
+    Paint,
+    Fill,
+    Rect,
+    Npc,
+}
+
+/// One undoable change, each entry is (tile index, old ID, new ID)
+type Edit = Vec<(usize, usize, usize)>;
+
+struct PlacedNPC {
+    id: i32,
+    dir: usize,
+    pos: Vec2i,
+    /// Dialogue the NPC says, the game looks it up in the level's table
+    key: String,
+}
+
+struct Assets {}
+
+struct State {
+    tileset: Rc<Tileset>,
+    tilemap: Tilemap,
+    map: Vec<usize>,
+    dims: (usize, usize),
+    sf: i32,
+    csv: PathBuf,
+
+    npcs: Vec<PlacedNPC>,
+    npc_path: PathBuf,
+    /// The level's dialogue table, NPCs without a line get a stub there on save
+    dlg_path: PathBuf,
+    npc_id: i32,
+    npc_dir: usize,
+
+    /// Cursor and camera in tiles
+    cursor: Vec2i,
+    cam: Vec2i,
+
+    tile: usize,
+    /// Autotile group painted instead of tile
+    group: Option<usize>,
+    tool: Tool,
+    corner: Option<Vec2i>,
+
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    /// Whether the last undo entry is a paint stroke that's still going
+    stroke: bool,
+
+    collision: bool,
+    dirty: bool,
+}
+
+impl State {
+    pub fn new() -> Self {
+        let exe_path = std::env::current_exe().unwrap();
+        let exe_dir = exe_path.parent().unwrap();
+
+        // map-editor [tilesheet.png] [map.csv] [width height] [dialogue.json]
+        let args: Vec<String> = std::env::args().collect();
+        let sheet = args.get(1).map_or(exe_dir.join("content/ts01.png"), PathBuf::from);
+        let csv = args.get(2).map_or(exe_dir.join("content/tm01.csv"), PathBuf::from);
+        let dims = match (args.get(3), args.get(4)) {
+            (Some(w), Some(h)) => (w.parse::<usize>().unwrap(), h.parse::<usize>().unwrap()),
+            _ => (56, 54),
+        };
+        let dlg_path = args.get(5).map_or(exe_dir.join("content/locale/en/dlg01.json"), PathBuf::from);
+
+        let tileset = Rc::new(Tileset::from_json(
+            Rc::new(Image::from_file(sheet.as_path())),
+            sheet.with_extension("json").as_path(),
+        ));
+        let tilemap = Tilemap::from_csv(Vec2i { x: 0, y: 0 }, dims, tileset.clone(), csv.as_path(), 2);
+        let map = tilemap.ground_ids();
+
+        let npc_path = csv.with_extension("npcs.json");
+        let npcs = if npc_path.exists() { load_npcs(&npc_path) } else { vec![] };
+
+        let mut s = Self {
+            tileset,
+            tilemap,
+            map,
+            dims,
+            sf: 2,
+            csv,
+            npcs,
+            npc_path,
+            dlg_path,
+            npc_id: 0,
+            npc_dir: DOWN,
+            cursor: Vec2i { x: 0, y: 0 },
+            cam: Vec2i { x: 0, y: 0 },
+            tile: 0,
+            group: None,
+            tool: Tool::Paint,
+            corner: None,
+            undo: vec![],
+            redo: vec![],
+            stroke: false,
+            collision: false,
+            dirty: false,
+        };
+        s.follow();
+        s
+    }
+
+    /// Change one tile of the map and the Tilemap drawn from it
+    fn set(&mut self, i: usize, id: usize) {
+        self.map[i] = id;
+        let pos = Vec2i { x: (i % self.dims.0) as i32, y: (i / self.dims.0) as i32 };
+        self.tilemap.set_tile(pos, id);
+    }
+
+    /// Keep the cursor in view
+    fn follow(&mut self) {
+        let max = Vec2i {
+            x: (self.dims.0 as i32 - VIEW_W).max(0),
+            y: (self.dims.1 as i32 - VIEW_H).max(0),
+        };
+        self.cam.x = (self.cursor.x - VIEW_W / 2).clamp(0, max.x);
+        self.cam.y = (self.cursor.y - VIEW_H / 2).clamp(0, max.y);
+        self.tilemap.position = Vec2i { x: -TILE_SZ * self.cam.x, y: -TILE_SZ * self.cam.y };
+    }
+
+    fn idx(&self, pos: Vec2i) -> usize {
+        pos.y as usize * self.dims.0 + pos.x as usize
+    }
+
+    /// Set every tile in positions to the selected tile or autotile as one undoable edit,
+    /// held painting adds to the stroke's edit instead
+    fn apply(&mut self, positions: Vec<Vec2i>) {
+        let mut new = self.map.clone();
+        for p in positions {
+            match self.group {
+                Some(g) => Tilemap::paint_autotile(&mut new, self.dims, p, &self.tileset.autotiles[g]),
+                None => new[self.idx(p)] = self.tile,
+            }
+        }
+        let edit: Edit = (0..new.len())
+            .filter(|i| new[*i] != self.map[*i])
+            .map(|i| (i, self.map[i], new[i]))
+            .collect();
+        if edit.is_empty() {
+            return;
+        }
+        for &(i, _, id) in edit.iter() {
+            self.set(i, id);
+        }
+        match self.undo.last_mut() {
+            Some(last) if self.stroke => {
+                // A tile painted twice in one stroke keeps the ID from before the stroke
+                for (i, old, id) in edit {
+                    match last.iter_mut().find(|(j, _, _)| *j == i) {
+                        Some(change) => change.2 = id,
+                        None => last.push((i, old, id)),
+                    }
+                }
+            },
+            _ => self.undo.push(edit),
+        }
+        self.stroke = self.tool == Tool::Paint;
+        self.redo.clear();
+        self.dirty = true;
+    }
+
+    fn undo(&mut self) {
+        self.stroke = false;
+        if let Some(edit) = self.undo.pop() {
+            for &(i, old, _) in edit.iter() {
+                self.set(i, old);
+            }
+            self.redo.push(edit);
+            self.dirty = true;
+        }
+    }
+
+    fn redo(&mut self) {
+        self.stroke = false;
+        if let Some(edit) = self.redo.pop() {
+            for &(i, _, id) in edit.iter() {
+                self.set(i, id);
+            }
+            self.undo.push(edit);
+            self.dirty = true;
+        }
+    }
+
+    /// Every tile connected to the cursor with the same ID
+    fn flood(&self, start: Vec2i) -> Vec<Vec2i> {
+        let target = self.map[self.idx(start)];
+        let mut seen = vec![false; self.map.len()];
+        let mut stack = vec![start];
+        let mut out = vec![];
+        while let Some(p) = stack.pop() {
+            if p.x < 0 || p.y < 0 || p.x >= self.dims.0 as i32 || p.y >= self.dims.1 as i32 {
+                continue;
+            }
+            let i = self.idx(p);
+            if seen[i] || self.map[i] != target {
+                continue;
+            }
+            seen[i] = true;
+            out.push(p);
+            for dir in [DOWN, UP, LEFT, RIGHT] {
+                let mut n = p;
+                n.walk(dir);
+                stack.push(n);
+            }
+        }
+        out
+    }
+
+    fn rect(a: Vec2i, b: Vec2i) -> Vec<Vec2i> {
+        let mut out = vec![];
+        for y in a.y.min(b.y)..=a.y.max(b.y) {
+            for x in a.x.min(b.x)..=a.x.max(b.x) {
+                out.push(Vec2i { x, y });
+            }
+        }
+        out
+    }
+
+    fn toggle_npc(&mut self) {
+        let pos = Vec2i { x: self.cursor.x / self.sf, y: self.cursor.y / self.sf };
+        match self.npcs.iter().position(|n| n.pos == pos) {
+            Some(i) => { self.npcs.remove(i); },
+            None => {
+                // New NPCs get a key nobody has yet, to be given dialogue under
+                let key = (1..)
+                    .map(|n| format!("NPC{}", n))
+                    .find(|key| self.npcs.iter().all(|n| &n.key != key))
+                    .unwrap();
+                self.npcs.push(PlacedNPC { id: self.npc_id, dir: self.npc_dir, pos, key })
+            },
+        }
+        self.dirty = true;
+    }
+
+    fn save(&mut self) {
+        self.tilemap.save_csv(self.csv.as_path());
+        save_npcs(&self.npc_path, &self.npcs);
+
+        // New keys need a line and a speaker name before the game can show them
+        let keys = self.npcs.iter().map(|n| n.key.as_str()).collect::<Vec<&str>>();
+        add_stubs(&self.dlg_path, &keys, |_| "...".to_string());
+        add_stubs(&self.dlg_path.with_file_name("names.json"), &keys, |key| key.to_string());
+        self.dirty = false;
+    }
+}
+
+fn dir_name(dir: usize) -> &'static str {
+    match dir {
+        DOWN => "down",
+        UP => "up",
+        LEFT => "left",
+        RIGHT => "right",
+        _ => panic!("Invalid direction")
+    }
+}
+
+fn load_npcs(path: &std::path::Path) -> Vec<PlacedNPC> {
+    let raw = fs::read_to_string(path).unwrap();
+    let defs: serde_json::Value = serde_json::from_str(&raw).unwrap();
+    defs.as_array()
+        .expect("NPCs must be a list")
+        .iter()
+        .map(|def| PlacedNPC {
+            id: def["id"].as_i64().unwrap_or(0) as i32,
+            dir: [DOWN, UP, LEFT, RIGHT]
+                .into_iter()
+                .find(|d| def["dir"].as_str() == Some(dir_name(*d)))
+                .unwrap_or(DOWN),
+            pos: Vec2i {
+                x: def["pos"][0].as_i64().unwrap() as i32,
+                y: def["pos"][1].as_i64().unwrap() as i32,
+            },
+            key: def["key"].as_str().unwrap_or_default().to_string(),
+        })
+        .collect()
+}
+
+fn save_npcs(path: &std::path::Path, npcs: &[PlacedNPC]) {
+    let defs: Vec<serde_json::Value> = npcs
+        .iter()
+        .map(|n| serde_json::json!({ "id": n.id, "dir": dir_name(n.dir), "pos": [n.pos.x, n.pos.y], "key": n.key }))
+        .collect();
+    if let Err(error) = fs::write(path, serde_json::to_string_pretty(&defs).unwrap()) {
+        panic!("Problem writing file: {:?}", error);
+    }
+}
+
+/// Add an entry to a JSON string table for each key it doesn't have yet.
+/// The entries are spliced in before the closing brace so the rest of the file keeps its layout.
+fn add_stubs(path: &std::path::Path, keys: &[&str], stub: fn(&str) -> String) {
+    let raw = fs::read_to_string(path).unwrap_or_else(|_| "{\n}\n".to_string());
+    let table: serde_json::Value = match serde_json::from_str(&raw) {
+        Ok(table) => table,
+        Err(error) => panic!("Problem reading file: {:?}", error),
+    };
+    let mut added: Vec<&str> = vec![];
+    for key in keys {
+        if table.get(key).is_none() && !added.contains(key) {
+            added.push(key);
+        }
+    }
+    if added.is_empty() {
+        return;
+    }
+
+    let end = raw.rfind('}').expect("String table must be an object");
+    let head = raw[..end].trim_end();
+    let mut out = head.to_string();
+    for (i, key) in added.iter().enumerate() {
+        if i > 0 || !head.ends_with('{') {
+            out.push(',');
+        }
+        out += &format!("\n    {}: {}", serde_json::json!(key), serde_json::json!(stub(key)));
+    }
+    out += "\n";
+    out += &raw[end..];
+    if let Err(error) = fs::write(path, out) {
+        panic!("Problem writing file: {:?}", error);
+    }
+}
+
+struct Editor {}
+
+fn main() {
+    engine::eng::go::<Editor>();
+}
+
+fn pressed(now_keys: &[bool], prev_keys: &[bool], key: usize) -> bool {
+    now_keys[key] && !prev_keys[key]
+}
+
+fn update_state(s: &mut State, now_keys: &[bool], prev_keys: &[bool]) {
+    let step = if now_keys[SHIFT] { 8 } else { 1 };
+
+    // CURSOR
+    for dir in [DOWN, UP, LEFT, RIGHT] {
+        if pressed(now_keys, prev_keys, dir) {
+            for _ in 0..step {
+                s.cursor.walk(dir);
+            }
+        }
+    }
+    s.cursor.x = s.cursor.x.clamp(0, s.dims.0 as i32 - 1);
+    s.cursor.y = s.cursor.y.clamp(0, s.dims.1 as i32 - 1);
+    s.follow();
+
+    // TOOLS
+    if pressed(now_keys, prev_keys, KEY_A + 15) { s.tool = Tool::Paint } // P
+    if pressed(now_keys, prev_keys, KEY_A + 5) { s.tool = Tool::Fill } // F
+    if pressed(now_keys, prev_keys, KEY_A + 17) { s.tool = Tool::Rect } // R
+    if pressed(now_keys, prev_keys, KEY_A + 13) { s.tool = Tool::Npc } // N
+    if s.tool != Tool::Rect || pressed(now_keys, prev_keys, ESCAPE) {
+        s.corner = None;
+    }
+
+    // PALETTE (Q/E, I picks the tile under the cursor)
+    let len = s.tileset.len();
+    if len > 0 {
+        if pressed(now_keys, prev_keys, KEY_A + 16) { s.tile = (s.tile + len - step % len) % len }
+        if pressed(now_keys, prev_keys, KEY_A + 4) { s.tile = (s.tile + step) % len }
+    }
+    if pressed(now_keys, prev_keys, KEY_A + 8) { s.tile = s.map[s.idx(s.cursor)] }
+
+    // AUTOTILES (G cycles through the groups and back to plain tiles)
+    if pressed(now_keys, prev_keys, KEY_A + 6) {
+        let count = s.tileset.autotiles.len();
+        s.group = match s.group {
+            None if count > 0 => Some(0),
+            Some(g) if g + 1 < count => Some(g + 1),
+            _ => None,
+        };
+    }
+
+    // NPCS (TAB picks the sprite, D turns it)
+    if pressed(now_keys, prev_keys, TAB) { s.npc_id = (s.npc_id + 1) % 5 }
+    if pressed(now_keys, prev_keys, KEY_A + 3) {
+        s.npc_dir = match s.npc_dir { DOWN => LEFT, LEFT => UP, UP => RIGHT, _ => DOWN };
+    }
+
+    // APPLY
+    if pressed(now_keys, prev_keys, SPACE) {
+        s.stroke = false;
+        match s.tool {
+            Tool::Paint => s.apply(vec![s.cursor]),
+            Tool::Fill => s.apply(s.flood(s.cursor)),
+            Tool::Rect => match s.corner.take() {
+                Some(corner) => s.apply(State::rect(corner, s.cursor)),
+                None => s.corner = Some(s.cursor),
+            },
+            Tool::Npc => s.toggle_npc(),
+        }
+    }
+    // Painting keeps going while space is held and the cursor moves, as one undoable stroke
+    if s.tool == Tool::Paint && now_keys[SPACE] && prev_keys[SPACE] {
+        s.apply(vec![s.cursor]);
+    }
+
+    // HISTORY, OVERLAY, SAVE (Z/Y, C, S)
+    if pressed(now_keys, prev_keys, KEY_A + 25) { s.undo() }
+    if pressed(now_keys, prev_keys, KEY_A + 24) { s.redo() }
+    if pressed(now_keys, prev_keys, KEY_A + 2) { s.collision = !s.collision }
+    if pressed(now_keys, prev_keys, KEY_A + 18) { s.save() }
+}
+
+/// A 1px outline around a rect
+fn outline(fb2d: &mut Image, r: Rect, c: Color) {
+    fb2d.draw_rect(&Rect { pos: r.pos, sz: Vec2i { x: r.sz.x, y: 1 } }, c);
+    fb2d.draw_rect(&Rect { pos: Vec2i { x: r.pos.x, y: r.bottom() - 1 }, sz: Vec2i { x: r.sz.x, y: 1 } }, c);
+    fb2d.draw_rect(&Rect { pos: r.pos, sz: Vec2i { x: 1, y: r.sz.y } }, c);
+    fb2d.draw_rect(&Rect { pos: Vec2i { x: r.pos.x + r.sz.x - 1, y: r.pos.y }, sz: Vec2i { x: 1, y: r.sz.y } }, c);
+}
+
+fn render_map(s: &State, fb2d: &mut Image) {
+    s.tilemap.draw(fb2d);
+    s.tilemap.draw_above(fb2d);
+    let cell = TILE_SZ * s.sf;
+
+    // Tint every movement cell that can't be walked on
+    if s.collision {
+        let mut tint = Image::new(Vec2i { x: cell, y: cell });
+        tint.clear((96, 0, 0, 96));
+        let (cols, rows) = s.tilemap.move_dims();
+        for y in 0..rows {
+            for x in 0..cols {
+                if !s.tilemap.can_move_to(Vec2i { x, y }) {
+                    let to = Vec2i { x: x * cell, y: y * cell } + s.tilemap.position;
+                    fb2d.bitblt(&tint, Rect { pos: Vec2i { x: 0, y: 0 }, sz: tint.sz }, to);
+                }
+            }
+        }
+    }
+
+    for npc in s.npcs.iter() {
+        let to = Vec2i { x: npc.pos.x * cell + 4, y: npc.pos.y * cell + 4 } + s.tilemap.position;
+        let c = (40 * (npc.id + 1) % 256) as u8;
+        fb2d.draw_ball(&Rect { pos: to, sz: Vec2i { x: 8, y: 8 } }, (255 - c, c, 0, 255));
+    }
+
+    let to_px = |p: Vec2i| Vec2i { x: (p.x - s.cam.x) * TILE_SZ, y: (p.y - s.cam.y) * TILE_SZ };
+    if let Some(corner) = s.corner {
+        let a = to_px(Vec2i { x: corner.x.min(s.cursor.x), y: corner.y.min(s.cursor.y) });
+        let b = to_px(Vec2i { x: corner.x.max(s.cursor.x) + 1, y: corner.y.max(s.cursor.y) + 1 });
+        outline(fb2d, Rect { pos: a, sz: b - a }, (0, 255, 255, 255));
+    }
+    let sz = if s.tool == Tool::Npc { cell } else { TILE_SZ };
+    let snap = if s.tool == Tool::Npc {
+        Vec2i { x: s.cursor.x - s.cursor.x % s.sf, y: s.cursor.y - s.cursor.y % s.sf }
+    } else {
+        s.cursor
+    };
+    outline(fb2d, Rect { pos: to_px(snap), sz: Vec2i { x: sz, y: sz } }, (255, 255, 0, 255));
+}
+
+fn render_palette(s: &State, fb2d: &mut Image) {
+    fb2d.draw_rect(
+        &Rect { pos: Vec2i { x: 0, y: PALETTE_Y }, sz: Vec2i { x: WIDTH as i32, y: HEIGHT as i32 - PALETTE_Y } },
+        (32, 32, 32, 255)
+    );
+
+    // STATUS: tool, NPC sprite and facing, overlay and unsaved changes
+    let tool_color = match s.tool {
+        Tool::Paint => (0, 200, 0, 255),
+        Tool::Fill => (0, 0, 200, 255),
+        Tool::Rect => (0, 200, 200, 255),
+        Tool::Npc => (200, 0, 200, 255),
+    };
+    fb2d.draw_rect(&Rect { pos: Vec2i { x: 1, y: PALETTE_Y + 1 }, sz: Vec2i { x: 6, y: 6 } }, tool_color);
+    for id in 0..=s.npc_id {
+        fb2d.draw_rect(&Rect { pos: Vec2i { x: 10 + 3 * id, y: PALETTE_Y + 2 }, sz: Vec2i { x: 2, y: 4 } }, (200, 200, 200, 255));
+    }
+    fb2d.draw_rect(
+        &Rect { pos: Vec2i { x: 28 + 2 * s.npc_dir as i32, y: PALETTE_Y + 2 }, sz: Vec2i { x: 2, y: 4 } },
+        (200, 200, 0, 255)
+    );
+    if s.collision {
+        fb2d.draw_rect(&Rect { pos: Vec2i { x: WIDTH as i32 - 16, y: PALETTE_Y + 1 }, sz: Vec2i { x: 6, y: 6 } }, (200, 0, 0, 255));
+    }
+    if s.dirty {
+        fb2d.draw_rect(&Rect { pos: Vec2i { x: WIDTH as i32 - 7, y: PALETTE_Y + 1 }, sz: Vec2i { x: 6, y: 6 } }, (255, 255, 255, 255));
+    }
+
+    // TILES around the selected one, or the selected autotile's variants
+    let y = PALETTE_Y + TILE_SZ + 4;
+    if let Some(g) = s.group {
+        for (slot, id) in s.tileset.autotiles[g].tiles.iter().take(VIEW_W as usize).enumerate() {
+            s.tileset.draw_tile(fb2d, *id, Vec2i { x: slot as i32 * TILE_SZ, y });
+        }
+        outline(
+            fb2d,
+            Rect { pos: Vec2i { x: -1, y: y - 1 }, sz: Vec2i { x: VIEW_W * TILE_SZ + 2, y: TILE_SZ + 2 } },
+            (0, 255, 255, 255)
+        );
+        return;
+    }
+    let len = s.tileset.len() as i32;
+    for slot in 0..VIEW_W {
+        let id = s.tile as i32 + slot - VIEW_W / 2;
+        if (0..len).contains(&id) {
+            s.tileset.draw_tile(fb2d, id as usize, Vec2i { x: slot * TILE_SZ, y });
+        }
+    }
+    outline(
+        fb2d,
+        Rect { pos: Vec2i { x: VIEW_W / 2 * TILE_SZ - 1, y: y - 1 }, sz: Vec2i { x: TILE_SZ + 2, y: TILE_SZ + 2 } },
+        (255, 255, 0, 255)
+    );
+}
+
+impl engine::eng::Game for Editor {
+    type Assets = Assets;
+    type State = State;
+
+    fn title() -> &'static str {
+        "Map Editor"
+    }
+
+    fn new() -> (State, Assets) {
+        (State::new(), Assets {})
+    }
+
+    fn update(s: &mut State, _assets: &mut Assets, now_keys: &[bool], prev_keys: &[bool]) {
+        update_state(s, now_keys, prev_keys);
+    }
+
+    fn render(s: &mut State, _assets: &mut Assets, fb2d: &mut Image) {
+        fb2d.clear((0, 0, 0, 255));
+        render_map(s, fb2d);
+        render_palette(s, fb2d);
+    }
+}
@@ -12,6 +12,7 @@
 use std::sync::Arc;
 
 use crate::types::{WIDTH, HEIGHT, WIN_WIDTH, WIN_HEIGHT};
+use crate::types::{DOWN, UP, LEFT, RIGHT, SPACE, ESCAPE, BACK, ENTER, SHIFT, TAB, KEY_A, NUM_KEYS};
 use crate::types::{Color, Image, Vec2i};
 use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess};
 use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents};
@@ -42,20 +43,31 @@ use winit::event::{Event, WindowEvent, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Window, WindowBuilder};
 
-fn index_from_keycode(kc: VirtualKeyCode) -> usize {
+fn index_from_keycode(kc: VirtualKeyCode) -> Option<usize> {
+    use VirtualKeyCode::*;
+    let letters = [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
     match kc {
-        VirtualKeyCode::Down => 0,
-        VirtualKeyCode::Up => 1,
-        VirtualKeyCode::Left => 2,
-        VirtualKeyCode::Right => 3,
-        VirtualKeyCode::Space => 4,
-        _ => 5
+        Down => Some(DOWN),
+        Up => Some(UP),
+        Left => Some(LEFT),
+        Right => Some(RIGHT),
+        Space => Some(SPACE),
+        Escape => Some(ESCAPE),
+        Back => Some(BACK),
+        Return => Some(ENTER),
+        LShift | RShift => Some(SHIFT),
+        Tab => Some(TAB),
+        _ => letters.iter().position(|l| *l == kc).map(|i| KEY_A + i)
     }
 }
 
 pub trait Game {
     type State;
     type Assets;
+    /// Window title
+    fn title() -> &'static str {
+        "A Man and His Bugs"
+    }
     fn new() -> (Self::State, Self::Assets);
     fn update(
         state: &mut Self::State,
@@ -69,7 +81,7 @@ pub trait Game {
 pub fn go<GameT: Game + 'static>() {
     let (mut state, mut assets) = GameT::new();
     let event_loop = EventLoop::new();
-    let mut vk = Vk::new();
+    let mut vk = Vk::new(GameT::title());
     let mut vk_state = VkState::new(&vk);
     let fb2d = Image {
         buffer: vec![(0, 0, 0, 255); (HEIGHT * WIDTH) as usize].into_boxed_slice(),
@@ -80,7 +92,7 @@ pub fn go<GameT: Game + 'static>() {
     };
     let mut fb_state = FBState::new(&vk, &vk_state, fb2d);
 
-    let mut now_keys = [false; 5];
+    let mut now_keys = [false; NUM_KEYS];
     let mut prev_keys = now_keys.clone();
 
     event_loop.run(move |event, _, control_flow| {
@@ -117,23 +129,16 @@ pub fn go<GameT: Game + 'static>() {
                 ..
             } => {
                 // It also binds these handy variable names!
-                match keycode {
-                    VirtualKeyCode::Down |
-                    VirtualKeyCode::Up |
-                    VirtualKeyCode::Left |
-                    VirtualKeyCode::Right |
-                    VirtualKeyCode::Space => {
-                        match state {
-                            winit::event::ElementState::Pressed => {
-                                // VirtualKeycode is an enum with a defined representation
-                                now_keys[index_from_keycode(keycode)] = true;
-                            }
-                            winit::event::ElementState::Released => {
-                                now_keys[index_from_keycode(keycode)] = false;
-                            }
+                if let Some(idx) = index_from_keycode(keycode) {
+                    match state {
+                        winit::event::ElementState::Pressed => {
+                            // VirtualKeycode is an enum with a defined representation
+                            now_keys[idx] = true;
                         }
-                    },
-                    _ => (),
+                        winit::event::ElementState::Released => {
+                            now_keys[idx] = false;
+                        }
+                    }
                 }
             }
             Event::MainEventsCleared => {
@@ -179,7 +184,7 @@ pub struct Vk {
 }
 
 impl Vk {
-    pub fn new() -> Self {
+    pub fn new(title: &str) -> Self {
         let required_extensions = vulkano_win::required_extensions();
         let instance = Instance::new(None, Version::V1_1, &required_extensions, None).unwrap();
         let event_loop = EventLoop::new();
@@ -187,7 +192,7 @@ impl Vk {
         let surface = WindowBuilder::new()
             .with_resizable(true)
             .with_inner_size(win_size)
-            .with_title("A Man and His Bugs")
+            .with_title(title)
             .build_vk_surface(&event_loop, instance.clone())
             .unwrap();
 
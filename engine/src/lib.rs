@@ -1,7 +1,11 @@
 pub mod animations;
 pub mod eng;
+pub mod light;
+pub mod locale;
 pub mod npc;
+pub mod path;
 pub mod sprite;
 pub mod text;
 pub mod tiles;
+pub mod trigger;
 pub mod types;
\ No newline at end of file
@@ -0,0 +1,150 @@
+use crate::tiles::parse_pos;
+use crate::types::{Image, Vec2i, MOVE_SZ};
+
+use std::fs;
+
+/// 4x4 ordered dither thresholds
+const BAYER: [[f32; 4]; 4] = [
+    [0.0 / 16.0, 8.0 / 16.0, 2.0 / 16.0, 10.0 / 16.0],
+    [12.0 / 16.0, 4.0 / 16.0, 14.0 / 16.0, 6.0 / 16.0],
+    [3.0 / 16.0, 11.0 / 16.0, 1.0 / 16.0, 9.0 / 16.0],
+    [15.0 / 16.0, 7.0 / 16.0, 13.0 / 16.0, 5.0 / 16.0],
+];
+
+/// A circle of light in map pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub pos: Vec2i,
+    /// Distance at which the light is gone
+    pub radius: f32,
+    /// Width of the fade at the edge of the radius, 0.0 is a hard edge
+    pub falloff: f32,
+}
+
+impl Light {
+    pub fn new(pos: Vec2i, radius: f32, falloff: f32) -> Self {
+        Self { pos, radius, falloff }
+    }
+
+    /// A light centred on a movement cell
+    pub fn at_cell(cell: Vec2i, radius: f32, falloff: f32) -> Self {
+        Self::new(
+            Vec2i { x: MOVE_SZ * cell.x + MOVE_SZ / 2, y: MOVE_SZ * cell.y + MOVE_SZ / 2 },
+            radius,
+            falloff
+        )
+    }
+
+    /// How lit a map pixel is, from 0.0 to 1.0
+    fn at(&self, x: i32, y: i32) -> f32 {
+        let dx = (x - self.pos.x) as f32 + 0.5;
+        let dy = (y - self.pos.y) as f32 + 0.5;
+        let d = (dx * dx + dy * dy).sqrt();
+        if d >= self.radius {
+            0.0
+        } else if self.falloff <= 0.0 || d <= self.radius - self.falloff {
+            1.0
+        } else {
+            (self.radius - d) / self.falloff
+        }
+    }
+}
+
+/// Darkness laid over a drawn map, lifted around lights
+pub struct Lighting {
+    /// How dark unlit pixels are, 0.0 is no darkness and 1.0 is black
+    pub ambient: f32,
+    /// Number of shades between lit and unlit, 0 blends smoothly
+    pub steps: usize,
+    /// Dither between shades instead of banding
+    pub dither: bool,
+    /// Lamps that belong to the map
+    pub lamps: Vec<Light>,
+}
+
+impl Lighting {
+    pub fn new(ambient: f32) -> Self {
+        Self {
+            ambient,
+            steps: 4,
+            dither: true,
+            lamps: vec![],
+        }
+    }
+
+    /// Reads {ambient, steps, dither, lamps: [{pos, radius, falloff}]}, lamp positions are movement cells
+    pub fn from_json(path: &std::path::Path) -> Self {
+        let content = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(error) => panic!("Problem reading file: {:?}", error),
+        };
+        let def: serde_json::Value = serde_json::from_str(&content).unwrap();
+
+        let mut lighting = Self::new(def["ambient"].as_f64().expect("Lighting needs an ambient") as f32);
+        if let Some(steps) = def["steps"].as_u64() {
+            lighting.steps = steps as usize;
+        }
+        if let Some(dither) = def["dither"].as_bool() {
+            lighting.dither = dither;
+        }
+        for lamp in def["lamps"].as_array().unwrap_or(&vec![]) {
+            lighting.lamps.push(Light::at_cell(
+                parse_pos(&lamp["pos"]).expect("Lamp needs a pos"),
+                lamp["radius"].as_f64().expect("Lamp needs a radius") as f32,
+                lamp["falloff"].as_f64().unwrap_or(0.0) as f32,
+            ));
+        }
+        lighting
+    }
+
+    /// Darken screen outside the lamps and extra lights, camera is where map pixel (0, 0) is drawn
+    pub fn apply(&self, screen: &mut Image, camera: Vec2i, extra: &[Light]) {
+        if self.ambient <= 0.0 {
+            return;
+        }
+
+        // Only lights that reach the screen
+        let (w, h) = screen.size();
+        let lights = self.lamps.iter()
+            .chain(extra.iter())
+            .filter(|l| {
+                let p = l.pos + camera;
+                let r = l.radius.ceil() as i32;
+                p.x + r >= 0 && p.y + r >= 0 && p.x - r < w && p.y - r < h
+            })
+            .collect::<Vec<&Light>>();
+
+        for y in 0..h {
+            for x in 0..w {
+                let lit = lights.iter()
+                    .map(|l| l.at(x - camera.x, y - camera.y))
+                    .fold(0.0, f32::max);
+                let dark = self.shade(self.ambient * (1.0 - lit), x, y);
+                if dark <= 0.0 {
+                    continue;
+                }
+
+                let keep = 1.0 - dark;
+                let px = &mut screen.buffer[(y * w + x) as usize];
+                px.0 = (px.0 as f32 * keep) as u8;
+                px.1 = (px.1 as f32 * keep) as u8;
+                px.2 = (px.2 as f32 * keep) as u8;
+            }
+        }
+    }
+
+    /// Snap darkness to one of the shades, dithered in screen space
+    fn shade(&self, dark: f32, x: i32, y: i32) -> f32 {
+        if self.steps == 0 {
+            return dark;
+        }
+
+        let level = dark / self.ambient * self.steps as f32;
+        let level = if self.dither {
+            (level + BAYER[(y & 3) as usize][(x & 3) as usize]).floor()
+        } else {
+            level.round()
+        };
+        self.ambient * level.clamp(0.0, self.steps as f32) / self.steps as f32
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Strings for one language in named tables of ID to text, read from a directory of <table>.json files
+pub struct Locale {
+    pub name: String,
+    tables: HashMap<String, HashMap<String, String>>,
+    /// Where tables and IDs this locale leaves out come from
+    fallback: Option<Box<Locale>>,
+}
+
+impl Locale {
+    /// Reads every .json file in dir as a flat object of ID to text
+    pub fn from_dir(dir: &Path, name: &str) -> Self {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) => panic!("Problem reading locale {}: {:?}", name, error),
+        };
+
+        let mut tables = HashMap::new();
+        for entry in entries {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let content = match fs::read_to_string(&path) {
+                Ok(s) => s,
+                Err(error) => panic!("Problem reading file: {:?}", error),
+            };
+            let table = serde_json::from_str::<HashMap<String, String>>(&content)
+                .unwrap_or_else(|error| panic!("Invalid string table {:?}: {}", path, error));
+            let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+            tables.insert(stem, table);
+        }
+
+        Self {
+            name: name.to_string(),
+            tables,
+            fallback: None,
+        }
+    }
+
+    /// Loads root/name over root/default, or just the default if there's no such locale
+    pub fn load(root: &Path, name: &str, default: &str) -> Self {
+        let fallback = Self::from_dir(root.join(default).as_path(), default);
+        if name == default || !root.join(name).is_dir() {
+            return fallback;
+        }
+        let mut locale = Self::from_dir(root.join(name).as_path(), name);
+        locale.fallback = Some(Box::new(fallback));
+        locale
+    }
+
+    /// Names of the locales in root, sorted
+    pub fn available(root: &Path) -> Vec<String> {
+        let mut names = match fs::read_dir(root) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_dir())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect::<Vec<String>>(),
+            Err(_) => vec![],
+        };
+        names.sort();
+        names
+    }
+
+    /// The text for id in table, from the fallback if this locale doesn't have it
+    pub fn get(&self, table: &str, id: &str) -> Option<&str> {
+        self.tables.get(table)
+            .and_then(|t| t.get(id))
+            .map(|s| s.as_str())
+            .or_else(|| self.fallback.as_ref()?.get(table, id))
+    }
+
+    /// Like get, but an ID nobody has reads as itself so it shows up in game
+    pub fn text(&self, table: &str, id: &str) -> String {
+        self.get(table, id).unwrap_or(id).to_string()
+    }
+
+    /// A whole table with the fallback's IDs filled in
+    pub fn table(&self, table: &str) -> HashMap<String, String> {
+        let mut merged = self.fallback.as_ref().map(|f| f.table(table)).unwrap_or_default();
+        if let Some(own) = self.tables.get(table) {
+            merged.extend(own.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        merged
+    }
+
+    /// Tables and IDs in this locale, sorted
+    pub fn ids(&self) -> Vec<(String, String)> {
+        let mut ids = self.tables.iter()
+            .flat_map(|(name, t)| t.keys().map(move |id| (name.clone(), id.clone())))
+            .collect::<Vec<(String, String)>>();
+        ids.sort();
+        ids
+    }
+
+    /// Tables and IDs the fallback has that this locale doesn't, sorted
+    pub fn missing(&self) -> Vec<(String, String)> {
+        let Some(fallback) = self.fallback.as_ref() else { return vec![] };
+        fallback.ids()
+            .into_iter()
+            .filter(|(table, id)| !self.tables.get(table).is_some_and(|t| t.contains_key(id)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_ids_fall_back_to_the_base_locale() {
+        let root = std::env::temp_dir().join(format!("locale-test-{}", std::process::id()));
+        for (file, json) in [
+            ("en/text.json", r#"{ "YES": "YES", "NO": "NO" }"#),
+            ("en/names.json", r#"{ "OAK": "PROF OAK" }"#),
+            ("fr/text.json", r#"{ "YES": "OUI" }"#),
+        ] {
+            fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            fs::write(root.join(file), json).unwrap();
+        }
+
+        let fr = Locale::load(&root, "fr", "en");
+        assert_eq!(Locale::available(&root), ["en", "fr"]);
+        assert_eq!(fr.get("text", "YES"), Some("OUI"));
+        assert_eq!(fr.get("text", "NO"), Some("NO"));
+        assert_eq!(fr.get("names", "OAK"), Some("PROF OAK"));
+        assert_eq!(fr.text("text", "MAYBE"), "MAYBE");
+        assert_eq!(fr.table("text")["YES"], "OUI");
+        assert_eq!(fr.table("text")["NO"], "NO");
+
+        let missing = [("names", "OAK"), ("text", "NO")].map(|(table, id)| (table.to_string(), id.to_string()));
+        assert_eq!(fr.missing(), missing);
+
+        // A locale that isn't there loads the base one, which has nothing to fall back on
+        let de = Locale::load(&root, "de", "en");
+        assert_eq!(de.name, "en");
+        assert!(de.missing().is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
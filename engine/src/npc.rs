@@ -8,17 +8,20 @@ pub struct NPC {
     cur_dir: i32,
     def_dir: i32,
     pos: Vec2i,
+    /// What the NPC is called in the dialogue history, may hold {NAME} markup
+    pub name: String,
     pub text: String,
     pub talked: bool
 }
 
 impl NPC {
-    pub fn new(id: i32, dir: i32, pos: Vec2i, text: String) -> Self {
+    pub fn new(id: i32, dir: i32, pos: Vec2i, name: String, text: String) -> Self {
         Self { 
             id,
             cur_dir: dir,
             def_dir: dir,
             pos,
+            name,
             text,
             talked: false
         }
@@ -49,12 +52,17 @@ pub struct NPCSet{
 
 impl NPCSet {
     pub fn new(path: &std::path::Path, npcs: Vec<NPC>, npc_sz: Vec2i, fin_text: String) -> Self {
+        Self::with_image(Image::from_file(path), npcs, npc_sz, fin_text)
+    }
+
+    /// Like new, with the spritesheet already loaded
+    pub fn with_image(image: Image, npcs: Vec<NPC>, npc_sz: Vec2i, fin_text: String) -> Self {
         let mut dict: HashMap<Vec2i, NPC> = HashMap::new();
         for npc in npcs {
             dict.insert(npc.pos, npc);
         }
         Self {
-            image: Image::from_file(path),
+            image,
             dict,
             npc_sz,
             fin: false,
@@ -90,4 +98,35 @@ impl NPCSet {
     pub fn at(&mut self, pos: Vec2i) -> Option<&mut NPC> {
         self.dict.get_mut(&pos)
     }
+
+    pub fn occupied(&self, pos: Vec2i) -> bool {
+        self.dict.contains_key(&pos)
+    }
+
+    /// Move every NPC by the same amount, for when the map's cells are renumbered
+    pub fn shift(&mut self, by: Vec2i) {
+        self.dict = self.dict.drain()
+            .map(|(pos, mut npc)| {
+                npc.pos = pos + by;
+                (npc.pos, npc)
+            })
+            .collect();
+    }
+
+    /// Move the NPC standing on from over to a free cell, turning it to face the way it went
+    pub fn move_to(&mut self, from: Vec2i, to: Vec2i) -> bool {
+        if self.occupied(to) {
+            return false;
+        }
+        match self.dict.remove(&from) {
+            Some(mut npc) => {
+                let d = to - from;
+                npc.cur_dir = if d.y > 0 { DOWN } else if d.y < 0 { UP } else if d.x < 0 { LEFT } else { RIGHT } as i32;
+                npc.pos = to;
+                self.dict.insert(to, npc);
+                true
+            },
+            None => false,
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,190 @@
+use crate::npc::NPCSet;
+use crate::tiles::{Step, Tile, Tilemap};
+use crate::types::{DOWN, UP, LEFT, RIGHT};
+use crate::types::Vec2i;
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Every step costs the same
+pub fn unit_cost(_tile: Tile) -> usize {
+    1
+}
+
+/// Shortest path over a map's movement cells, walking around solid tiles and NPCs.
+/// Returns the cells to step through, ending on `to`, or None if it can't be reached.
+/// A ledge hop or a slide over ice is a single entry, the cell it comes down on.
+pub fn find_path(map: &Tilemap, npcs: &NPCSet, from: Vec2i, to: Vec2i) -> Option<Vec<Vec2i>> {
+    find_path_weighted(map, npcs, from, to, unit_cost)
+}
+
+/// Like find_path, but stepping onto a cell costs `cost` of its tile (at least 1), so
+/// e.g. tall grass or slow terrain can be avoided when there's another way round.
+/// The goal is never blocked by an NPC standing on it, so you can path up to someone.
+pub fn find_path_weighted(
+    map: &Tilemap,
+    npcs: &NPCSet,
+    from: Vec2i,
+    to: Vec2i,
+    cost: fn(Tile) -> usize
+) -> Option<Vec<Vec2i>> {
+    if !map.in_bounds(from) || !map.in_bounds(to) {
+        return None;
+    }
+
+    let guess = |p: Vec2i| ((p.x - to.x).abs() + (p.y - to.y).abs()) as usize;
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<Vec2i, usize> = HashMap::new();
+    let mut came_from: HashMap<Vec2i, Vec2i> = HashMap::new();
+
+    open.push(Reverse((guess(from), 0, from.x, from.y)));
+    best.insert(from, 0);
+
+    while let Some(Reverse((_, spent, x, y))) = open.pop() {
+        let pos = Vec2i { x, y };
+        if pos == to {
+            let mut path = vec![];
+            let mut cur = to;
+            while cur != from {
+                path.push(cur);
+                cur = came_from[&cur];
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if spent > best[&pos] {
+            continue;
+        }
+
+        for dir in [DOWN, UP, LEFT, RIGHT] {
+            let step = map.step(pos, dir);
+            let (next, steps) = match step {
+                Step::Walk | Step::Grass | Step::Slow | Step::Slide => {
+                    let mut next = pos;
+                    next.walk(dir);
+                    (next, 0)
+                },
+                // Hopping a ledge also passes over the ledge cell
+                Step::Jump(land) => (land, 1),
+                Step::Blocked | Step::Water | Step::TalkOver(_) => continue,
+            };
+            if next != to && npcs.occupied(next) {
+                continue;
+            }
+            // Ice carries on to wherever the slide stops, the cells on the way can't be stopped on
+            let (next, steps) = match step {
+                Step::Slide if !npcs.occupied(next) => slide(map, npcs, next, dir),
+                _ => (next, steps),
+            };
+
+            let spent = spent + steps + cost(map.cell(next)).max(1);
+            if best.get(&next).is_none_or(|b| spent < *b) {
+                best.insert(next, spent);
+                came_from.insert(next, pos);
+                open.push(Reverse((spent + guess(next), spent, next.x, next.y)));
+            }
+        }
+    }
+    None
+}
+
+/// Where a slide that has just reached the ice at pos comes to rest, and how many more cells it
+/// crosses to get there. Like the player, it stops on the first cell that isn't ice, at the end
+/// of a ledge hop, or on the ice in front of anything that blocks it, NPCs included.
+fn slide(map: &Tilemap, npcs: &NPCSet, mut pos: Vec2i, dir: usize) -> (Vec2i, usize) {
+    let mut crossed = 0;
+    loop {
+        let mut next = pos;
+        next.walk(dir);
+        match map.step(pos, dir) {
+            Step::Slide if !npcs.occupied(next) => {
+                pos = next;
+                crossed += 1;
+            },
+            Step::Walk | Step::Grass | Step::Slow if !npcs.occupied(next) => return (next, crossed + 1),
+            Step::Jump(land) if !npcs.occupied(next) && !npcs.occupied(land) => return (land, crossed + 2),
+            _ => return (pos, crossed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc::NPC;
+    use crate::tiles::Tileset;
+    use crate::types::Image;
+    use std::rc::Rc;
+
+    /// A map at one tile per movement cell: 0 walks, 1 is solid, 2 is a ledge hopped down and 3 is ice
+    fn map(w: usize, ids: Vec<usize>) -> Tilemap {
+        let tiles = vec![
+            Tile::new(false),
+            Tile::new(true),
+            Tile { ledge: Some(DOWN), ..Default::default() },
+            Tile { ice: true, ..Default::default() },
+        ];
+        let tileset = Rc::new(Tileset::new(tiles, Rc::new(Image::new(Vec2i { x: 32, y: 8 }))));
+        Tilemap::new(Vec2i { x: 0, y: 0 }, (w, ids.len() / w), tileset, ids, 1)
+    }
+
+    fn npcs(cells: &[Vec2i]) -> NPCSet {
+        let npcs = cells.iter().map(|pos| NPC::new(0, 0, *pos, String::new(), String::new())).collect();
+        NPCSet::with_image(Image::new(Vec2i { x: 16, y: 16 }), npcs, Vec2i { x: 16, y: 16 }, String::new())
+    }
+
+    fn cells(xys: &[(i32, i32)]) -> Vec<Vec2i> {
+        xys.iter().map(|&(x, y)| Vec2i { x, y }).collect()
+    }
+
+    #[test]
+    fn straight_route_steps_through_every_cell() {
+        let map = map(5, vec![0; 5]);
+        let path = find_path(&map, &npcs(&[]), Vec2i { x: 0, y: 0 }, Vec2i { x: 3, y: 0 });
+        assert_eq!(path, Some(cells(&[(1, 0), (2, 0), (3, 0)])));
+        assert_eq!(find_path(&map, &npcs(&[]), Vec2i { x: 2, y: 0 }, Vec2i { x: 2, y: 0 }), Some(vec![]));
+    }
+
+    #[test]
+    fn walls_and_npcs_are_walked_around() {
+        let map = map(3, vec![
+            0, 1, 0,
+            0, 1, 0,
+            0, 0, 0,
+        ]);
+        let (from, to) = (Vec2i { x: 0, y: 0 }, Vec2i { x: 2, y: 0 });
+        let path = find_path(&map, &npcs(&[]), from, to);
+        assert_eq!(path, Some(cells(&[(0, 1), (0, 2), (1, 2), (2, 2), (2, 1), (2, 0)])));
+
+        // Someone standing in the only gap blocks it, but someone on the goal doesn't
+        assert_eq!(find_path(&map, &npcs(&[Vec2i { x: 1, y: 2 }]), from, to), None);
+        assert_eq!(find_path(&map, &npcs(&[to]), from, to).map(|path| path.len()), Some(6));
+    }
+
+    #[test]
+    fn ledges_are_hopped_one_way() {
+        let map = map(3, vec![
+            0, 0, 0,
+            2, 1, 1,
+            0, 0, 0,
+        ]);
+        let (top, bottom) = (Vec2i { x: 2, y: 0 }, Vec2i { x: 0, y: 2 });
+        assert_eq!(find_path(&map, &npcs(&[]), top, bottom), Some(cells(&[(1, 0), (0, 0), (0, 2)])));
+        assert_eq!(find_path(&map, &npcs(&[]), bottom, top), None);
+    }
+
+    #[test]
+    fn ice_slides_to_where_it_stops() {
+        let ice = map(6, vec![0, 3, 3, 3, 0, 0]);
+        let start = Vec2i { x: 0, y: 0 };
+        // One entry for the whole slide, and the ice it crosses can't be stopped on
+        assert_eq!(find_path(&ice, &npcs(&[]), start, Vec2i { x: 5, y: 0 }), Some(cells(&[(4, 0), (5, 0)])));
+        assert_eq!(find_path(&ice, &npcs(&[]), start, Vec2i { x: 2, y: 0 }), None);
+
+        // Sliding into a wall, or up to someone, stops on the ice in front of them
+        let walled = map(4, vec![0, 3, 3, 1]);
+        assert_eq!(find_path(&walled, &npcs(&[]), start, Vec2i { x: 2, y: 0 }), Some(cells(&[(2, 0)])));
+        let path = find_path(&ice, &npcs(&[Vec2i { x: 3, y: 0 }]), start, Vec2i { x: 2, y: 0 });
+        assert_eq!(path, Some(cells(&[(2, 0)])));
+    }
+}
@@ -1,205 +1,1236 @@
-use crate::types::{HEIGHT, TILE_SZ, TSPEED, WIPENUM};
-use crate::types::{Image, Rect, Vec2i};
+use crate::tiles::parse_pos;
+use crate::types::{BACK, DOWN, ENTER, ESCAPE, HEIGHT, KEY_A, SPACE, TILE_SZ, TSPEED, UP, WIPENUM};
+use crate::types::{Color, Image, Rect, Vec2i};
 
+use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
 
 pub struct Charcode(usize);
 
+/// Where a glyph is on the sheet and how far it moves the pen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GlyphDef {
+    pub rect: Rect,
+    pub advance: i32,
+}
+
+impl GlyphDef {
+    /// The part of the rect that's drawn, cut at the advance so a glyph's cell doesn't cover the next one
+    pub fn drawn(&self) -> Rect {
+        Rect { pos: self.rect.pos, sz: Vec2i { x: self.rect.sz.x.min(self.advance).max(0), y: self.rect.sz.y } }
+    }
+}
+
+/// Glyphs on a text sheet and the characters they draw, glyphs are referred to by index
+pub struct Font {
+    pub glyphs: Vec<GlyphDef>,
+    /// Glyph for each single character
+    chars: HashMap<char, usize>,
+    /// Character sequences drawn as one glyph, like 'd, longest first
+    ligatures: Vec<(Vec<char>, usize)>,
+    /// Characters drawn with an opening then a closing glyph, like quotes
+    pairs: HashMap<char, (usize, usize)>,
+    /// Window frame themes, ten glyphs each in the order of the frame pieces
+    themes: Vec<[usize; 10]>,
+    /// Glyph for blank cells
+    pub space: usize,
+}
+
+impl Font {
+    /// Reads a font descriptor, positions are in cells of "cell" pixels:
+    /// "space" [x, y], "rows" [{at, chars, advance}], "glyphs" {char: {at, size, advance}},
+    /// "ligatures" {sequence: [x, y]}, "pairs" {char: [open, close]}, "advances" {char: px}
+    /// and "themes" [[fill, top left, top right, bottom right, bottom left, top, bottom, left, right, arrow]].
+    pub fn from_json(path: &std::path::Path) -> Self {
+        Self::try_from_json(path).unwrap_or_else(|error| panic!("Problem reading {:?}: {}", path, error))
+    }
+
+    /// Like from_json, returning what's wrong with the descriptor instead of panicking
+    pub fn try_from_json(path: &std::path::Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let defs: serde_json::Value = serde_json::from_str(&content).map_err(|error| error.to_string())?;
+
+        let cell = parse_pos(&defs["cell"]).unwrap_or(Vec2i { x: TILE_SZ, y: TILE_SZ });
+        let cell_at = |v: &serde_json::Value, what: &str| -> Result<Rect, String> {
+            let at = parse_pos(v).ok_or(format!("{} needs a cell [x, y]", what))?;
+            Ok(Rect { pos: Vec2i { x: cell.x * at.x, y: cell.y * at.y }, sz: cell })
+        };
+
+        let mut font = Self {
+            glyphs: vec![GlyphDef { rect: cell_at(&defs["space"], "space")?, advance: cell.x }],
+            chars: HashMap::new(),
+            ligatures: vec![],
+            pairs: HashMap::new(),
+            themes: vec![],
+            space: 0,
+        };
+        font.chars.insert(' ', 0);
+
+        for row in defs["rows"].as_array().unwrap_or(&vec![]) {
+            let at = parse_pos(&row["at"]).ok_or("rows need an at [x, y]")?;
+            let advance = row["advance"].as_i64().map_or(cell.x, |a| a as i32);
+            let chars = row["chars"].as_str().ok_or("rows need chars")?;
+            for (i, c) in chars.chars().enumerate() {
+                let pos = Vec2i { x: cell.x * (at.x + i as i32), y: cell.y * at.y };
+                let id = font.add(GlyphDef { rect: Rect { pos, sz: cell }, advance });
+                font.chars.insert(c, id);
+            }
+        }
+        for (c, def) in defs["glyphs"].as_object().into_iter().flatten() {
+            let c = single(c).ok_or(format!("glyph \"{}\" must be one character", c))?;
+            let mut rect = cell_at(&def["at"], "glyph")?;
+            if let Some(sz) = parse_pos(&def["size"]) {
+                rect.sz = sz;
+            }
+            let advance = def["advance"].as_i64().map_or(rect.sz.x, |a| a as i32);
+            let id = font.add(GlyphDef { rect, advance });
+            font.chars.insert(c, id);
+        }
+        for (seq, at) in defs["ligatures"].as_object().into_iter().flatten() {
+            let id = font.add(GlyphDef { rect: cell_at(at, "ligature")?, advance: cell.x });
+            font.ligatures.push((seq.chars().collect(), id));
+        }
+        font.ligatures.sort_by_key(|(seq, _)| std::cmp::Reverse(seq.len()));
+        for (c, def) in defs["pairs"].as_object().into_iter().flatten() {
+            let c = single(c).ok_or(format!("pair \"{}\" must be one character", c))?;
+            let pair = def.as_array().filter(|p| p.len() == 2).ok_or("pairs need an open and a close glyph")?;
+            let open = font.add(GlyphDef { rect: cell_at(&pair[0], "pair")?, advance: cell.x });
+            let close = font.add(GlyphDef { rect: cell_at(&pair[1], "pair")?, advance: cell.x });
+            font.pairs.insert(c, (open, close));
+        }
+        for (c, px) in defs["advances"].as_object().into_iter().flatten() {
+            let id = font.lookup(c).ok_or(format!("no glyph \"{}\" to set an advance for", c))?;
+            font.glyphs[id].advance = px.as_i64().ok_or("advances must be numbers")? as i32;
+        }
+        for theme in defs["themes"].as_array().into_iter().flatten() {
+            let pieces = theme.as_array().filter(|t| t.len() == 10).ok_or("themes need 10 frame pieces")?;
+            let mut ids = [0; 10];
+            for (i, piece) in pieces.iter().enumerate() {
+                ids[i] = font.add(GlyphDef { rect: cell_at(piece, "theme")?, advance: cell.x });
+            }
+            font.themes.push(ids);
+        }
+        Ok(font)
+    }
+
+    fn add(&mut self, glyph: GlyphDef) -> usize {
+        self.glyphs.push(glyph);
+        self.glyphs.len() - 1
+    }
+
+    /// The glyph for a character or ligature sequence
+    pub fn lookup(&self, s: &str) -> Option<usize> {
+        let seq = s.chars().collect::<Vec<char>>();
+        match single(s) {
+            Some(c) => self.chars.get(&c).copied(),
+            None => self.ligatures.iter().find(|(l, _)| *l == seq).map(|(_, id)| *id),
+        }
+    }
+
+    /// Read the glyph at the start of chars, flipping open for paired characters.
+    /// Returns None for a character the font doesn't have, and how many chars were read.
+    pub fn next_glyph(&self, chars: &[char], open: &mut Vec<char>) -> (Option<usize>, usize) {
+        if let Some((seq, id)) = self.ligatures.iter().find(|(seq, _)| chars.starts_with(seq)) {
+            return (Some(*id), seq.len());
+        }
+        let c = chars[0];
+        if let Some((o, cl)) = self.pairs.get(&c) {
+            return match open.iter().position(|p| *p == c) {
+                Some(i) => { open.remove(i); (Some(*cl), 1) },
+                None => { open.push(c); (Some(*o), 1) },
+            };
+        }
+        (self.chars.get(&c).copied(), 1)
+    }
+
+    pub fn themes(&self) -> usize {
+        self.themes.len()
+    }
+}
+
+fn single(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    let c = chars.next()?;
+    chars.next().is_none().then_some(c)
+}
+
 pub struct Textset {
     image: Rc<Image>,
-    get_coord: fn(usize) -> Vec2i
+    pub font: Font,
+    /// The colour letters are drawn in on the sheet, replaced by colour markup
+    pub ink: Color
 }
 
 impl Textset {
-    pub fn new(path: &std::path::Path, get_coord: fn(usize) -> Vec2i) -> Self {
+    pub fn new(path: &std::path::Path, font: Font) -> Self {
         Self {
             image: Rc::new(Image::from_file(path)),
-            get_coord
+            font,
+            ink: (0, 0, 0, 255)
+        }
+    }
+
+    /// Draw a glyph with its ink swapped for color
+    fn draw_glyph(&self, screen: &mut Image, glyph: Glyph, to: Vec2i) {
+        match glyph.color {
+            Some(color) => screen.bitblt_recolored(&self.image, self.get_rect(glyph.code), to, self.ink, color),
+            None => screen.bitblt(&self.image, self.get_rect(glyph.code), to),
         }
     }
     
-    fn get_rect(&self, c: usize) -> Rect {
-        Rect {
-            pos: (self.get_coord)(c),
-            sz: Vec2i {
-                x: TILE_SZ,
-                y: TILE_SZ,
+    fn get_rect(&self, id: usize) -> Rect {
+        self.font.glyphs[id].drawn()
+    }
+}
+
+/// A {tag} in dialogue text
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Markup {
+    /// {color:RRGGBB} recolours the ink of the text after it, {color} goes back to the sheet's own
+    Color(Option<Color>),
+    /// {pause:N} waits N more ticks before the next glyph
+    Pause(usize),
+    /// {speed:N} reveals a glyph every N ticks, {speed} goes back to TSPEED
+    Speed(usize),
+    /// {page} starts a new page
+    Page,
+    /// {NAME} is replaced by the value of an upper case variable
+    Var(String),
+}
+
+impl Markup {
+    /// Read the inside of a tag, None if it's malformed
+    pub fn parse(tag: &str) -> Option<Markup> {
+        let (name, arg) = match tag.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (tag, None),
+        };
+        match (name, arg) {
+            ("color", None) => Some(Markup::Color(None)),
+            ("color", Some(hex)) if hex.len() == 6 => {
+                let c = u32::from_str_radix(hex, 16).ok()?;
+                Some(Markup::Color(Some(((c >> 16) as u8, (c >> 8) as u8, c as u8, 255))))
+            },
+            ("pause", Some(n)) => n.parse().ok().map(Markup::Pause),
+            ("speed", None) => Some(Markup::Speed(TSPEED)),
+            ("speed", Some(n)) => n.parse().ok().filter(|n| *n > 0).map(Markup::Speed),
+            ("page", None) => Some(Markup::Page),
+            (name, None) if !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') => {
+                Some(Markup::Var(name.to_string()))
             },
+            _ => None
         }
     }
 }
 
-pub struct Textbox {
+/// A glyph laid out on a page
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Glyph {
+    code: usize,
+    color: Option<Color>,
+    /// Tick after the page starts that the glyph shows up
+    at: usize,
+    /// Pixels from the start of the row
+    x: i32,
+}
+
+/// A glyph as it's read, before it has a place on a page
+#[derive(Clone, Copy)]
+struct Mark {
+    code: usize,
+    /// Last character read for the glyph
+    c: char,
+    color: Option<Color>,
+    speed: usize,
+    pause: usize,
+}
+
+/// Rows of glyphs and how many ticks each page of lines rows takes to reveal
+struct Layout {
+    rows: Vec<Vec<Glyph>>,
+    pages: Vec<usize>,
+}
+
+impl Layout {
+    /// Wrap text into rows width pixels wide, each sentence starting on a new page of lines rows.
+    /// Characters the font doesn't have are left blank and markup that can't be read is shown as it is.
+    fn new(s: &str, font: &Font, vars: &HashMap<String, String>, width: i32, lines: usize) -> Self {
+        let (words, breaks) = Self::words(s, font, vars);
+
+        // ROWS -> a word and the space after it have to end before the last cell, kept for the page arrow
+        let room = width - TILE_SZ;
+        let advance = |m: &Mark| font.glyphs[m.code].advance;
+        let space = font.glyphs[font.space].advance;
+        let hyphen = font.lookup("-");
+        let hyphen_w = hyphen.map_or(0, |h| font.glyphs[h].advance);
+        let mut nl = false;
+        let mut pen = 0;
+        let mut line = Vec::new();
+        let mut r: Vec<Vec<(i32, Mark)>> = Vec::new();
+        for (wi, w) in words.into_iter().enumerate() {
+            nl |= breaks.contains(&wi) && !line.is_empty();
+            if w.is_empty() {
+                continue;
+            }
+            let len = w.iter().map(advance).sum::<i32>();
+            if nl {
+                r.push(std::mem::take(&mut line));
+                r.resize(r.len().div_ceil(lines) * lines, vec![]);
+                pen = 0;
+            } else if pen > 0 && pen + len + space > room && len + space <= room {
+                r.push(std::mem::take(&mut line));
+                pen = 0;
+            }
+
+            // Words too long for a row are broken with a hyphen, rows narrower than a glyph still take one
+            let mut rest = &w[..];
+            while !rest.is_empty() && pen + rest.iter().map(advance).sum::<i32>() + space > room {
+                let mut n = 0;
+                let mut px = pen;
+                while n < rest.len() && px + advance(&rest[n]) + hyphen_w <= room {
+                    px += advance(&rest[n]);
+                    n += 1;
+                }
+                if n > 0 || pen == 0 {
+                    for m in rest[..n.max(1)].iter() {
+                        line.push((pen, *m));
+                        pen += advance(m);
+                    }
+                    rest = &rest[n.max(1)..];
+                    if let (Some(code), false) = (hyphen, rest.is_empty()) {
+                        let m = line[line.len() - 1].1;
+                        line.push((pen, Mark { code, c: '-', pause: 0, ..m }));
+                    }
+                }
+                r.push(std::mem::take(&mut line));
+                pen = 0;
+            }
+
+            for m in rest.iter() {
+                line.push((pen, *m));
+                pen += advance(m);
+            }
+            pen += space;
+            nl = matches!(w[w.len()-1].c, '!' | '?' | '.' | '*');
+        }
+        r.push(line);
+        r.resize(r.len().div_ceil(lines) * lines, vec![]);
+
+        // TIMING -> blanks fill the gaps and take as long as the glyph before them, huge pauses and speeds saturate
+        let blank = font.glyphs[font.space].drawn().sz.x.max(1);
+        let mut speed = TSPEED;
+        let mut pages = Vec::new();
+        let rows = r.chunks(lines)
+            .flat_map(|page| {
+                let mut t = 0;
+                let page = page.iter()
+                    .map(|row| {
+                        let mut glyphs = Vec::new();
+                        let mut x = 0;
+                        for (at, m) in row.iter().map(|(at, m)| (*at, Some(m))).chain([(width, None)]) {
+                            while x < at {
+                                glyphs.push(Glyph { code: font.space, color: None, at: t, x });
+                                t = t.saturating_add(speed);
+                                x += blank;
+                            }
+                            if let Some(m) = m {
+                                t = t.saturating_add(m.pause);
+                                speed = m.speed;
+                                glyphs.push(Glyph { code: m.code, color: m.color, at: t, x: at });
+                                t = t.saturating_add(speed);
+                                x = at + advance(m);
+                            }
+                        }
+                        glyphs
+                    })
+                    .collect::<Vec<Vec<Glyph>>>();
+                pages.push(t);
+                page
+            })
+            .collect();
+        Self { rows, pages }
+    }
+
+    /// Read text into words of marks, and the words that {page} puts on a new page
+    fn words(s: &str, font: &Font, vars: &HashMap<String, String>) -> (Vec<Vec<Mark>>, Vec<usize>) {
+        let mut words: Vec<Vec<Mark>> = Vec::with_capacity(s.len());
+        let mut word = Vec::new();
+        let mut breaks = Vec::new();
+        let mut color = None;
+        let mut speed = TSPEED;
+        let mut pause: usize = 0;
+        let mut open = Vec::new();
+        let push = |word: &mut Vec<Mark>, code: Option<usize>, c: char, color: Option<Color>, speed: usize, pause: &mut usize| {
+            word.push(Mark { code: code.unwrap_or(font.space), c, color, speed, pause: *pause });
+            *pause = 0;
+        };
+
+        let mut chars = s.chars().collect::<Vec<char>>();
+        // Variable values are read up to here without markup
+        let mut plain = 0;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            i += 1;
+            let tag = (c == '{' && i > plain)
+                .then(|| chars[i..].iter().position(|c| *c == '}'))
+                .flatten()
+                .map(|len| (len, chars[i..i + len].iter().collect::<String>()));
+            if let Some((len, tag)) = tag {
+                i += len + 1;
+                match Markup::parse(&tag) {
+                    Some(Markup::Color(c)) => color = c,
+                    Some(Markup::Pause(n)) => pause = pause.saturating_add(n),
+                    Some(Markup::Speed(n)) => speed = n,
+                    Some(Markup::Page) => {
+                        words.push(std::mem::take(&mut word));
+                        breaks.push(words.len());
+                    },
+                    Some(Markup::Var(name)) if vars.contains_key(&name) => {
+                        let value = &vars[&name];
+                        chars.splice(i..i, value.chars());
+                        plain = i + value.chars().count();
+                    },
+                    _ => for c in format!("{{{}}}", tag).chars() {
+                        push(&mut word, font.lookup(&c.to_string()), c, color, speed, &mut pause);
+                    },
+                }
+            } else if c.is_whitespace() {
+                words.push(std::mem::take(&mut word));
+            } else {
+                let (code, len) = font.next_glyph(&chars[i - 1..], &mut open);
+                i += len - 1;
+                push(&mut word, code, chars[i - 1], color, speed, &mut pause);
+            }
+        }
+        words.push(word);
+        (words, breaks)
+    }
+
+    /// Glyphs for text on one row without wrapping, and how wide they are
+    fn line(s: &str, font: &Font, vars: &HashMap<String, String>) -> (Vec<Glyph>, i32) {
+        let space = font.glyphs[font.space].advance;
+        let mut glyphs = Vec::new();
+        let mut pen = 0;
+        for w in Self::words(s, font, vars).0.into_iter().filter(|w| !w.is_empty()) {
+            if pen > 0 {
+                pen += space;
+            }
+            for m in w {
+                glyphs.push(Glyph { code: m.code, color: m.color, at: 0, x: pen });
+                pen += font.glyphs[m.code].advance;
+            }
+        }
+        (glyphs, pen)
+    }
+}
+
+/// Frame pieces of a theme, in the order the font lists them
+const FILL: usize = 0;
+const TOP_LEFT: usize = 1;
+const TOP_RIGHT: usize = 2;
+const BOTTOM_RIGHT: usize = 3;
+const BOTTOM_LEFT: usize = 4;
+const TOP: usize = 5;
+const BOTTOM: usize = 6;
+const LEFT: usize = 7;
+const RIGHT: usize = 8;
+const ARROW: usize = 9;
+
+/// A nine-slice window, a frame of tiles from a Textset around a filled middle
+pub struct Window {
     pub position: Vec2i,
-    dims: (usize, usize),
+    /// Size in tiles, frame included
+    pub dims: (usize, usize),
     textset: Rc<Textset>,
+    /// Frame theme as glyph rects, ordered like the constants above
     base: Vec<Rect>,
-    rows: Vec<[usize; 20]>,
+}
+
+impl Window {
+    pub fn new(textset: Rc<Textset>, position: Vec2i, dims: (usize, usize)) -> Self {
+        assert!(dims.0 >= 2 && dims.1 >= 2, "Window needs room for its frame");
+        let mut window = Self {
+            position,
+            dims,
+            textset,
+            base: vec![],
+        };
+        window.set_theme(0);
+        window
+    }
+
+    /// Use one of the font's frame themes
+    pub fn set_theme(&mut self, theme: usize) {
+        let font = &self.textset.font;
+        assert!(theme < font.themes(), "Font has no frame theme {}", theme);
+        self.base = font.themes[theme].iter().map(|id| self.textset.get_rect(*id)).collect::<Vec<Rect>>();
+    }
+
+    /// Draw the frame and clear the middle
+    pub fn draw(&self, screen: &mut Image) {
+        self.draw_frame(screen);
+        for y in 1..self.dims.1 - 1 {
+            for x in 1..self.dims.0 - 1 {
+                self.draw_fill(screen, (x, y));
+            }
+        }
+    }
+
+    /// Draw only the frame, leaving the middle to the caller
+    pub fn draw_frame(&self, screen: &mut Image) {
+        let (w, h) = (self.dims.0 - 1, self.dims.1 - 1);
+        for y in 0..=h {
+            for x in 0..=w {
+                let piece = match (x, y) {
+                    (0, 0) => TOP_LEFT,
+                    (x, 0) if x == w => TOP_RIGHT,
+                    (x, y) if x == w && y == h => BOTTOM_RIGHT,
+                    (0, y) if y == h => BOTTOM_LEFT,
+                    (_, 0) => TOP,
+                    (_, y) if y == h => BOTTOM,
+                    (0, _) => LEFT,
+                    (x, _) if x == w => RIGHT,
+                    _ => continue,
+                };
+                self.blit(screen, self.base[piece], (x, y));
+            }
+        }
+    }
+
+    /// Clear the tile at (x, y)
+    pub fn draw_fill(&self, screen: &mut Image, at: (usize, usize)) {
+        self.blit(screen, self.base[FILL], at);
+    }
+
+    /// Draw one of the font's glyphs on the tile at (x, y), counted from the window's top left corner
+    pub fn draw_glyph(&self, screen: &mut Image, id: usize, at: (usize, usize)) {
+        self.blit(screen, self.textset.get_rect(id), at);
+    }
+
+    /// Draw a laid out glyph on tile row y, placed from the first tile inside the frame
+    fn draw_marked(&self, screen: &mut Image, glyph: Glyph, y: usize) {
+        self.textset.draw_glyph(screen, glyph, self.tile_pos((1, y)) + Vec2i { x: glyph.x, y: 0 });
+    }
+
+    /// Draw a "▶" on the tile at (x, y), or the theme's arrow if the font has none
+    fn draw_cursor(&self, screen: &mut Image, at: (usize, usize)) {
+        match self.textset.font.lookup("▶") {
+            Some(id) => self.draw_glyph(screen, id, at),
+            None => self.draw_arrow(screen, at),
+        }
+    }
+
+    /// Draw the theme's "more" arrow on the tile at (x, y)
+    pub fn draw_arrow(&self, screen: &mut Image, at: (usize, usize)) {
+        self.blit(screen, self.base[ARROW], at);
+    }
+
+    fn blit(&self, screen: &mut Image, frame: Rect, at: (usize, usize)) {
+        screen.bitblt(&self.textset.image, frame, self.tile_pos(at));
+    }
+
+    fn tile_pos(&self, (x, y): (usize, usize)) -> Vec2i {
+        Vec2i {
+            x: (x * TILE_SZ as usize) as i32 + self.position.x,
+            y: (y * TILE_SZ as usize) as i32 + self.position.y,
+        }
+    }
+}
+
+/// How fast text is revealed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextSpeed {
+    Slow,
+    #[default]
+    Medium,
+    Fast,
+    /// Whole pages at once
+    Instant,
+}
+
+impl TextSpeed {
+    /// Half ticks revealed a frame, None for the whole page
+    fn halves(self) -> Option<usize> {
+        match self {
+            TextSpeed::Slow => Some(1),
+            TextSpeed::Medium => Some(2),
+            TextSpeed::Fast => Some(4),
+            TextSpeed::Instant => None,
+        }
+    }
+}
+
+/// How many times faster text reveals while hurried
+const HURRY: usize = 4;
+
+/// Move cptr on by a frame at speed towards len, carrying a half tick in half
+fn reveal(cptr: &mut usize, half: &mut usize, speed: TextSpeed, hurry: bool, len: usize) {
+    if *cptr >= len {
+        return;
+    }
+    match speed.halves() {
+        Some(h) => {
+            let h = *half + if hurry { h * HURRY } else { h };
+            *cptr = cptr.saturating_add(h / 2).min(len);
+            *half = h % 2;
+        },
+        None => *cptr = len,
+    }
+}
+
+/// One set_text as far as it was read
+struct Said {
+    speaker: Option<String>,
+    /// The speaker's name as it read when the text was set
+    name: Vec<Glyph>,
+    /// Rows of every page shown, blank rows left out
+    rows: Vec<Vec<Glyph>>,
+}
+
+/// Conversations kept by a new Textbox
+const HISTORY: usize = 64;
+
+/// What a Textbox has shown, oldest first
+pub struct History {
+    said: VecDeque<Said>,
+    /// Conversations kept, the oldest are dropped past this
+    pub limit: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            said: VecDeque::new(),
+            limit,
+        }
+    }
+
+    /// Conversations kept
+    pub fn len(&self) -> usize {
+        self.said.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.said.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.said.clear();
+    }
+
+    fn start(&mut self, speaker: Option<String>, name: Vec<Glyph>) {
+        self.said.push_back(Said { speaker, name, rows: vec![] });
+        while self.said.len() > self.limit.max(1) {
+            self.said.pop_front();
+        }
+    }
+
+    fn add(&mut self, rows: &[Vec<Glyph>], space: usize) {
+        if let Some(said) = self.said.back_mut() {
+            let shown = rows.iter().filter(|row| row.iter().any(|g| g.code != space));
+            said.rows.extend(shown.cloned());
+        }
+    }
+}
+
+/// Paged text in a Window, one blank tile row above each line of text
+pub struct Textbox {
+    pub window: Window,
+    /// Lines of text shown at once
+    lines: usize,
+    layout: Layout,
+    /// First row of the current page
     rptr: usize,
-    pub cptr: usize
+    pub cptr: usize,
+    /// Half a tick left over from the last frame
+    half: usize,
+    pub speed: TextSpeed,
+    /// Set while the player holds the key that speeds text up
+    pub hurry: bool,
+    /// Values for {NAME} markup, read when text is set
+    pub vars: HashMap<String, String>,
+    /// Who says the text, read with vars when text is set
+    pub speaker: Option<String>,
+    /// Every page shown so far
+    pub history: History,
 }
 
 impl Textbox {
     pub fn new(textset: Rc<Textset>) -> Self {
-        let base = (0usize..=9).map(|x| textset.get_rect(x)).collect::<Vec<Rect>>();
+        Self::with_size(textset, Vec2i { x: 0, y: HEIGHT as i32 - 48 }, (22, 6))
+    }
+
+    /// A textbox dims tiles big, frame included, holding (dims.1 - 2) / 2 lines
+    pub fn with_size(textset: Rc<Textset>, position: Vec2i, dims: (usize, usize)) -> Self {
+        assert!(dims.1 >= 4, "Textbox needs room for a line of text");
         Self {
-            position: Vec2i { x: 0, y: HEIGHT as i32 - 48 },
-            dims: (22, 6),
-            textset,
-            base,
-            rows: vec![],
-            rptr: 1,
-            cptr: 0
+            window: Window::new(textset, position, dims),
+            lines: (dims.1 - 2) / 2,
+            layout: Layout { rows: vec![], pages: vec![] },
+            rptr: 0,
+            cptr: 0,
+            half: 0,
+            speed: TextSpeed::default(),
+            hurry: false,
+            vars: HashMap::new(),
+            speaker: None,
+            history: History::new(HISTORY),
         }
     }
 
     pub fn set_base(&mut self, level: usize) {
-        self.base = (10*level..=(10*level+9)).map(|x| self.textset.get_rect(x)).collect::<Vec<Rect>>();
+        self.window.set_theme(level);
+    }
+
+    /// Glyphs per line
+    pub fn width(&self) -> usize {
+        self.window.dims.0 - 2
     }
 
     pub fn set_text(&mut self, text: &str) {
         self.cptr = 0;
-        self.rows = Textbox::parse(text);
-        self.rptr = 1;
+        self.half = 0;
+        self.layout = Layout::new(text, &self.window.textset.font, &self.vars, (self.width() * TILE_SZ as usize) as i32, self.lines);
+        self.rptr = 0;
+        let name = self.speaker.as_ref()
+            .map(|speaker| Layout::line(&format!("{}:", speaker), &self.window.textset.font, &self.vars).0)
+            .unwrap_or_default();
+        self.history.start(self.speaker.clone(), name);
+        self.record();
     }
 
     pub fn scroll(&mut self) -> bool {
         self.cptr = 0;
-        self.rptr += 2;
-        self.rptr < self.rows.len()
+        self.half = 0;
+        self.rptr += self.lines;
+        self.record();
+        self.rptr < self.layout.rows.len()
+    }
+
+    /// Keep the page just shown in the history
+    fn record(&mut self) {
+        let rows = &self.layout.rows;
+        let page = &rows[self.rptr.min(rows.len())..(self.rptr + self.lines).min(rows.len())];
+        self.history.add(page, self.window.textset.font.space);
+    }
+
+    /// Whether the current page is the last one
+    pub fn is_last(&self) -> bool {
+        self.rptr + self.lines >= self.layout.rows.len()
+    }
+
+    /// Reveal the page a frame further
+    pub fn tick(&mut self) {
+        let len = self.page_len();
+        reveal(&mut self.cptr, &mut self.half, self.speed, self.hurry, len);
+    }
+
+    /// Show the rest of the page now
+    pub fn finish(&mut self) {
+        self.cptr = self.cptr.max(self.page_len());
+    }
+
+    pub fn is_revealed(&self) -> bool {
+        self.cptr >= self.page_len()
+    }
+
+    /// Ticks until the whole page is revealed
+    pub fn page_len(&self) -> usize {
+        self.layout.pages.get(self.rptr / self.lines).copied().unwrap_or(0)
     }
 
     pub fn draw(&self, screen: &mut Image) {
-        const W: usize = 21;
-        const H: usize = 5;
-
-        let is_last = self.rptr >= self.rows.len()-1;
-        for y in 0..self.dims.1 {
-            let ypx = (y * TILE_SZ as usize) as i32 + self.position.y;
-            for x in 0..self.dims.0 {
-                let xpx = (x * TILE_SZ as usize) as i32 + self.position.x;
-
-                let frame = match (x,y) {
-                    (0,0)  => self.base[1], // top left
-                    (W,0)  => self.base[2], // top right
-                    (W,H)  => self.base[3], // bottom right
-                    (0,H)  => self.base[4], // bottom left
-                    (_,0)  => self.base[5], // top horizontal
-                    (_,H)  => self.base[6], // bottom horizontal
-                    (0,_)  => self.base[7], // left vertical
-                    (W,_)  => self.base[8], // right vertical
-                    (20,4) => if !is_last { self.base[9] } else { self.base[0] },
-                    (_,2)  => if TSPEED*(x - 1) <= self.cptr { // top row
-                                        self.textset.get_rect(self.rows.get(self.rptr-1).unwrap()[x-1])
-                                    } else { 
-                                        self.base[0] 
-                                    },
-                    (_,4)  => if TSPEED*(x + 19) <= self.cptr { // btm row
-                                        self.textset.get_rect(self.rows.get(self.rptr).unwrap()[x-1])
-                                    } else { 
-                                        self.base[0] 
-                                    },
-                    _      => self.base[0], // space
-                };
+        self.window.draw_frame(screen);
+
+        let width = self.width();
+        let px = (width * TILE_SZ as usize) as i32;
+        for y in 1..self.window.dims.1 - 1 {
+            // Glyphs that would stick out of the window aren't drawn
+            let glyphs = self.revealed(y)
+                .into_iter()
+                .filter(|g| g.x + self.window.textset.get_rect(g.code).sz.x <= px)
+                .collect::<Vec<Glyph>>();
+
+            // Tiles the glyphs don't cover are cleared first
+            let mut covered = vec![false; px as usize];
+            for glyph in glyphs.iter() {
+                let w = self.window.textset.get_rect(glyph.code).sz.x;
+                for x in glyph.x.max(0)..glyph.x + w {
+                    covered[x as usize] = true;
+                }
+            }
+            for (x, tile) in covered.chunks(TILE_SZ as usize).enumerate() {
+                if !tile.iter().all(|c| *c) {
+                    self.window.draw_fill(screen, (x + 1, y));
+                }
+            }
 
-                screen.bitblt(&self.textset.image, frame, Vec2i { x: xpx, y: ypx });
+            for glyph in glyphs {
+                self.window.draw_marked(screen, glyph, y);
             }
         }
-    }
 
-    fn parse(s: &str) -> Vec<[usize; 20]> {
-        let mut a = false;
-        let mut q = false;
-        let mut word = Vec::new();
-        let mut words = Vec::with_capacity(s.len());
-        for c in s.chars() {
-            if c.is_whitespace() {
-                words.push(word.clone());
-                word = Vec::new();
-            } else if a {
-                word.push(match c {
-                    'd' => 256,
-                    'l' => 257,
-                    's' => 258,
-                    't' => 259,
-                    'v' => 260,
-                    'r' => 261,
-                    'm' => 262,
-                    _ => panic!("Invalid char trailing an apostrophe")
-                });
-                a = false;
-            } else if c == '\'' {
-                a = true
-            } else if c == '"' {
-                if q { word.push(264); q = false }
-                else { word.push(263); q = true }
+        // Last tile of the page
+        if width > 0 {
+            if self.is_last() {
+                self.window.draw_fill(screen, (width, 2 * self.lines));
             } else {
-                word.push(c as usize);
+                self.window.draw_arrow(screen, (width, 2 * self.lines));
             }
         }
-        words.push(word.clone());
-        let mut nl = false;
-        let mut li = 0;
-        let mut line = [0; 20];
-        let mut r = Vec::new();
-        for w in words {
-            if nl {
-                r.push(line);
-                if r.len()%2 == 1 { r.push([0;20]) }
-                li = 0;
-                line = [0; 20];
-            } else if li + w.len() + 1 >= 20 {
-                r.push(line);
-                li = 0;
-                line = [0; 20];
-            }
-             
-            for (wi,c) in w.iter().enumerate() {
-                line[li + wi] = *c;
-            }
-            li += w.len() + 1;
-            nl = matches!(w[w.len()-1], 33 | 63 | 46 | 42);
+    }
+
+    /// The revealed glyphs on window row y, text sits on every other row
+    fn revealed(&self, y: usize) -> Vec<Glyph> {
+        if y % 2 == 1 || y / 2 > self.lines {
+            return vec![];
         }
-        r.push(line);
-        if r.len() % 2 > 0 {
-            r.push([0; 20]);
+        self.layout.rows.get(self.rptr + y / 2 - 1)
+            .map(|row| row.iter().filter(|g| g.at <= self.cptr).copied().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// How a menu was closed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuResult {
+    /// The item at this index was picked
+    Chosen(usize),
+    Cancelled,
+}
+
+/// A list of choices in a Window, one per other row, picked with a cursor
+pub struct Menu {
+    pub window: Window,
+    items: Vec<String>,
+    glyphs: Vec<Vec<Glyph>>,
+    /// Item the cursor is on
+    pub cursor: usize,
+    /// First item shown, lists longer than the window scroll
+    top: usize,
+    /// Items shown at once
+    rows: usize,
+    /// Moving past either end goes round to the other
+    pub wrap: bool,
+    /// Whether ESCAPE or BACK closes the menu
+    pub cancel: bool,
+}
+
+impl Menu {
+    /// A menu just wide enough for its items, showing up to rows of them at once
+    pub fn new(textset: Rc<Textset>, position: Vec2i, items: &[&str], rows: usize) -> Self {
+        assert!(!items.is_empty(), "Menu needs an item");
+        let rows = rows.clamp(1, items.len());
+        let vars = HashMap::new();
+        let (glyphs, widths): (Vec<Vec<Glyph>>, Vec<i32>) = items.iter()
+            .map(|item| Layout::line(item, &textset.font, &vars))
+            .unzip();
+        let widest = widths.into_iter().max().unwrap_or(0);
+
+        // Frame, cursor, items, and a column for the more arrow when they scroll
+        let more = if rows < items.len() { 1 } else { 0 };
+        let cols = 3 + (widest as usize).div_ceil(TILE_SZ as usize) + more;
+        Self {
+            window: Window::new(textset, position, (cols, 2 * rows + 1)),
+            items: items.iter().map(|item| item.to_string()).collect(),
+            glyphs,
+            cursor: 0,
+            top: 0,
+            rows,
+            wrap: true,
+            cancel: true,
+        }
+    }
+
+    /// A YES/NO prompt, YES is item 0
+    pub fn yes_no(textset: Rc<Textset>, position: Vec2i) -> Self {
+        Self::new(textset, position, &["YES", "NO"], 2)
+    }
+
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    /// Put the cursor back on the first item
+    pub fn reset(&mut self) {
+        self.cursor = 0;
+        self.top = 0;
+    }
+
+    /// Move the cursor with UP and DOWN, pick with SPACE or ENTER and cancel with ESCAPE or BACK
+    pub fn update(&mut self, now_keys: &[bool], prev_keys: &[bool]) -> Option<MenuResult> {
+        let pressed = |key: usize| now_keys[key] && !prev_keys[key];
+        let last = self.items.len() - 1;
+        if pressed(UP) {
+            self.cursor = match self.cursor {
+                0 if self.wrap => last,
+                0 => 0,
+                c => c - 1,
+            };
+        } else if pressed(DOWN) {
+            self.cursor = match self.cursor {
+                c if c == last && self.wrap => 0,
+                c if c == last => last,
+                c => c + 1,
+            };
+        }
+
+        // Keep the cursor on screen
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + self.rows {
+            self.top = self.cursor + 1 - self.rows;
+        }
+
+        if pressed(SPACE) || pressed(ENTER) {
+            Some(MenuResult::Chosen(self.cursor))
+        } else if self.cancel && (pressed(ESCAPE) || pressed(BACK)) {
+            Some(MenuResult::Cancelled)
+        } else {
+            None
+        }
+    }
+
+    pub fn draw(&self, screen: &mut Image) {
+        self.window.draw(screen);
+
+        // Items start on the tile after the cursor
+        let px = (self.window.dims.0 as i32 - 3) * TILE_SZ;
+        for (row, i) in (self.top..self.items.len()).take(self.rows).enumerate() {
+            let y = 2 * row + 1;
+            if i == self.cursor {
+                self.window.draw_cursor(screen, (1, y));
+            }
+            for glyph in self.glyphs[i].iter() {
+                if glyph.x + self.window.textset.get_rect(glyph.code).sz.x <= px {
+                    self.window.draw_marked(screen, Glyph { x: glyph.x + TILE_SZ, ..*glyph }, y);
+                }
+            }
+        }
+
+        if self.top + self.rows < self.items.len() {
+            self.window.draw_arrow(screen, (self.window.dims.0 - 2, 2 * self.rows - 1));
+        }
+    }
+}
+
+/// Characters a row of a NameEntry holds
+const NAME_COLS: usize = 9;
+
+/// A name picked a character at a time from a grid of glyphs, with a row for DEL and END below it
+pub struct NameEntry {
+    pub window: Window,
+    prompt: Vec<Glyph>,
+    chars: Vec<char>,
+    /// Glyphs of each character, laid out from the start of its cell
+    cells: Vec<Vec<Glyph>>,
+    /// DEL and END
+    labels: [Vec<Glyph>; 2],
+    /// (column, row) the cursor is on, the row after the last one of characters holds DEL and END
+    pub cursor: (usize, usize),
+    pub name: String,
+    /// Characters a name can have
+    pub max_len: usize,
+}
+
+impl NameEntry {
+    /// A grid of chars the font has, labels are what DEL and END read
+    pub fn new(textset: Rc<Textset>, position: Vec2i, chars: &str, labels: [&str; 2], max_len: usize) -> Self {
+        assert!(max_len > 0, "Name needs room for a character");
+        let vars = HashMap::new();
+        let chars = chars.chars()
+            .filter(|c| textset.font.lookup(&c.to_string()).is_some())
+            .collect::<Vec<char>>();
+        assert!(!chars.is_empty(), "NameEntry needs a character the font has");
+        let cells = chars.iter().map(|c| Layout::line(&c.to_string(), &textset.font, &vars).0).collect();
+        let labels = labels.map(|label| Layout::line(label, &textset.font, &vars).0);
+
+        // Prompt, name, then a cursor and a character to every two tiles
+        let rows = chars.len().div_ceil(NAME_COLS);
+        Self {
+            window: Window::new(textset, position, (2 + 2 * NAME_COLS, 7 + 2 * rows)),
+            prompt: vec![],
+            chars,
+            cells,
+            labels,
+            cursor: (0, 0),
+            name: String::new(),
+            max_len,
+        }
+    }
+
+    /// What's shown above the name
+    pub fn set_prompt(&mut self, prompt: &str) {
+        self.prompt = Layout::line(prompt, &self.window.textset.font, &HashMap::new()).0;
+    }
+
+    /// Clear the name and put the cursor back on the first character
+    pub fn reset(&mut self) {
+        self.name.clear();
+        self.cursor = (0, 0);
+    }
+
+    /// Rows of characters, DEL and END are on the row after them
+    fn rows(&self) -> usize {
+        self.chars.len().div_ceil(NAME_COLS)
+    }
+
+    fn row_len(&self, y: usize) -> usize {
+        if y == self.rows() {
+            2
+        } else {
+            (self.chars.len() - y * NAME_COLS).min(NAME_COLS)
+        }
+    }
+
+    /// Grid column of the cell at (x, y), END sits halfway along
+    fn column(&self, (x, y): (usize, usize)) -> usize {
+        if y == self.rows() { x * (NAME_COLS / 2) } else { x }
+    }
+
+    /// The cell on row y nearest to column
+    fn at_column(&self, column: usize, y: usize) -> usize {
+        if y == self.rows() {
+            (column >= NAME_COLS / 2) as usize
+        } else {
+            column.min(self.row_len(y) - 1)
+        }
+    }
+
+    /// Add c to the name, moving on to END once it's full
+    fn push(&mut self, c: char) {
+        if self.name.chars().count() < self.max_len {
+            self.name.push(c);
+        }
+        if self.name.chars().count() >= self.max_len {
+            self.cursor = (1, self.rows());
+        }
+    }
+
+    fn confirm(&self) -> Option<String> {
+        if self.name.is_empty() { None } else { Some(self.name.clone()) }
+    }
+
+    /// Move with the arrows, pick with SPACE, type letters straight in, delete with BACK and finish with ENTER or END.
+    /// Gives the name once it's done, a name can't be empty.
+    pub fn update(&mut self, now_keys: &[bool], prev_keys: &[bool]) -> Option<String> {
+        let pressed = |key: usize| now_keys[key] && !prev_keys[key];
+        let (x, y) = self.cursor;
+        let count = self.rows() + 1;
+        if pressed(UP) || pressed(DOWN) {
+            let column = self.column(self.cursor);
+            let y = if pressed(UP) { (y + count - 1) % count } else { (y + 1) % count };
+            self.cursor = (self.at_column(column, y), y);
+        } else if pressed(crate::types::LEFT) {
+            let len = self.row_len(y);
+            self.cursor.0 = (x + len - 1) % len;
+        } else if pressed(crate::types::RIGHT) {
+            self.cursor.0 = (x + 1) % self.row_len(y);
+        }
+
+        // Letters the grid has can be typed
+        for i in 0..26 {
+            if pressed(KEY_A + i) {
+                let upper = (b'A' + i as u8) as char;
+                let c = [upper, upper.to_ascii_lowercase()].into_iter().find(|c| self.chars.contains(c));
+                if let Some(c) = c {
+                    self.push(c);
+                }
+            }
+        }
+
+        if pressed(BACK) {
+            self.name.pop();
+        }
+        if pressed(ENTER) {
+            return self.confirm();
+        }
+        if pressed(SPACE) {
+            let (x, y) = self.cursor;
+            match (x, y == self.rows()) {
+                (0, true) => { self.name.pop(); },
+                (_, true) => return self.confirm(),
+                (x, false) => self.push(self.chars[y * NAME_COLS + x]),
+            }
+        }
+        None
+    }
+
+    pub fn draw(&self, screen: &mut Image) {
+        self.window.draw(screen);
+
+        for glyph in self.prompt.iter() {
+            self.window.draw_marked(screen, *glyph, 1);
+        }
+        let (name, _) = Layout::line(&self.name, &self.window.textset.font, &HashMap::new());
+        for glyph in name {
+            self.window.draw_marked(screen, Glyph { x: glyph.x + TILE_SZ, ..glyph }, 3);
+        }
+
+        // Each cell is a tile for the cursor then one for its glyphs
+        let cell = |column: usize, glyph: &Glyph| Glyph { x: glyph.x + (2 * column as i32 + 1) * TILE_SZ, ..*glyph };
+        for (i, glyphs) in self.cells.iter().enumerate() {
+            let y = 5 + 2 * (i / NAME_COLS);
+            for glyph in glyphs.iter() {
+                self.window.draw_marked(screen, cell(i % NAME_COLS, glyph), y);
+            }
+        }
+        let y = 5 + 2 * self.rows();
+        for (x, label) in self.labels.iter().enumerate() {
+            for glyph in label.iter() {
+                self.window.draw_marked(screen, cell(self.column((x, self.rows())), glyph), y);
+            }
+        }
+
+        let column = self.column(self.cursor);
+        self.window.draw_cursor(screen, (1 + 2 * column, 5 + 2 * self.cursor.1));
+    }
+}
+
+/// A History in a Window, a line per tile row under the name of whoever said it
+pub struct Log {
+    pub window: Window,
+    lines: Vec<Vec<Glyph>>,
+    /// First line shown
+    top: usize,
+}
+
+impl Log {
+    pub fn new(textset: Rc<Textset>, position: Vec2i, dims: (usize, usize)) -> Self {
+        assert!(dims.1 >= 3, "Log needs room for a line");
+        Self {
+            window: Window::new(textset, position, dims),
+            lines: vec![],
+            top: 0,
+        }
+    }
+
+    /// Lines shown at once
+    fn rows(&self) -> usize {
+        self.window.dims.1 - 2
+    }
+
+    /// Take the lines from history and scroll to the newest
+    pub fn open(&mut self, history: &History) {
+        self.lines.clear();
+        let mut last: Option<&str> = None;
+        for said in history.said.iter() {
+            if !self.lines.is_empty() {
+                self.lines.push(vec![]);
+            }
+            // A name heads each run of things the same speaker said
+            if let Some(speaker) = said.speaker.as_deref() {
+                if last != Some(speaker) {
+                    self.lines.push(said.name.clone());
+                }
+            }
+            last = said.speaker.as_deref();
+            self.lines.extend(said.rows.iter().cloned());
+        }
+        self.top = self.lines.len().saturating_sub(self.rows());
+    }
+
+    /// Scroll a line with UP and DOWN or a page with LEFT and RIGHT, true once ESCAPE or BACK closes it
+    pub fn update(&mut self, now_keys: &[bool], prev_keys: &[bool]) -> bool {
+        let pressed = |key: usize| now_keys[key] && !prev_keys[key];
+        let rows = self.rows();
+        let bottom = self.lines.len().saturating_sub(rows);
+        if pressed(UP) {
+            self.top = self.top.saturating_sub(1);
+        } else if pressed(DOWN) {
+            self.top = (self.top + 1).min(bottom);
+        } else if pressed(crate::types::LEFT) {
+            self.top = self.top.saturating_sub(rows);
+        } else if pressed(crate::types::RIGHT) {
+            self.top = (self.top + rows).min(bottom);
+        }
+        pressed(ESCAPE) || pressed(BACK)
+    }
+
+    pub fn draw(&self, screen: &mut Image) {
+        self.window.draw(screen);
+
+        let px = (self.window.dims.0 as i32 - 2) * TILE_SZ;
+        for (row, line) in self.lines.iter().skip(self.top).take(self.rows()).enumerate() {
+            for glyph in line.iter() {
+                if glyph.x + self.window.textset.get_rect(glyph.code).sz.x <= px {
+                    self.window.draw_marked(screen, *glyph, row + 1);
+                }
+            }
+        }
+
+        if self.top + self.rows() < self.lines.len() {
+            self.window.draw_arrow(screen, (self.window.dims.0 - 2, self.window.dims.1 - 2));
         }
-        r
     }
 }
 
 pub struct Textscreen {
     pub position: Vec2i,
     textset: Rc<Textset>,
-    rows: Vec<[usize; 20]>,
+    layout: Layout,
     rptr: usize,
     pub cptr: usize,
-    pub animc: i32
+    /// Half a tick left over from the last frame
+    half: usize,
+    pub speed: TextSpeed,
+    /// Set while the player holds the key that speeds text up
+    pub hurry: bool,
+    pub animc: i32,
+    /// Values for {NAME} markup, read when text is set
+    pub vars: HashMap<String, String>
 }
 
 impl Textscreen {
     pub fn new(textset: Rc<Textset>, text: &str) -> Self {
-        let rows = Textbox::parse(text);
+        let vars = HashMap::new();
         Self {
             position: Vec2i { x: 0, y: HEIGHT as i32 - 48 },
+            layout: Layout::new(text, &textset.font, &vars, 20 * TILE_SZ, 2),
             textset,
-            rows,
             rptr: 1,
             cptr: 0,
-            animc: 242
+            half: 0,
+            speed: TextSpeed::default(),
+            hurry: false,
+            animc: 242,
+            vars
         }
     }
 
     pub fn set_text(&mut self, text: &str) {
         self.cptr = 0;
-        self.rows = Textbox::parse(text);
+        self.half = 0;
+        self.layout = Layout::new(text, &self.textset.font, &self.vars, 20 * TILE_SZ, 2);
         self.rptr = 1;
     }
 
     pub fn scroll(&mut self) -> bool {
         self.cptr = 0;
+        self.half = 0;
         self.rptr += 2;
-        self.rptr < self.rows.len()
+        self.rptr < self.layout.rows.len()
+    }
+
+    /// Reveal the page a frame further
+    pub fn tick(&mut self) {
+        let len = self.page_len();
+        reveal(&mut self.cptr, &mut self.half, self.speed, self.hurry, len);
+    }
+
+    /// Show the rest of the page now
+    pub fn finish(&mut self) {
+        self.cptr = self.cptr.max(self.page_len());
+    }
+
+    pub fn is_revealed(&self) -> bool {
+        self.cptr >= self.page_len()
+    }
+
+    /// Ticks until the whole page is revealed
+    pub fn page_len(&self) -> usize {
+        self.layout.pages.get(self.rptr / 2).copied().unwrap_or(0)
     }
 
     pub fn anim(&mut self, screen: &mut Image) {
@@ -220,24 +1251,85 @@ impl Textscreen {
 
     pub fn draw(&self, screen: &mut Image) {
         screen.clear((0,0,0,0));
-        for x in 1..=20 {
-            let xpx = (x * TILE_SZ as usize) as i32 + self.position.x;
-
-            if TSPEED*(x - 1) <= self.cptr { // top row
-                screen.bitblt(
-                    &self.textset.image, 
-                    self.textset.get_rect(self.rows.get(self.rptr-1).unwrap()[x-1]), 
-                    Vec2i { x: xpx, y: 80 }
-                );
+        for (row, y) in [(self.rptr - 1, 80), (self.rptr, 88)] { // top and btm rows
+            for glyph in self.layout.rows[row].iter() {
+                if glyph.at <= self.cptr && glyph.x + self.textset.get_rect(glyph.code).sz.x <= 20 * TILE_SZ {
+                    let xpx = TILE_SZ + self.position.x + glyph.x;
+                    self.textset.draw_glyph(screen, *glyph, Vec2i { x: xpx, y });
+                }
             }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Glyphs 6px wide for lowercase letters and the characters markup uses
+    fn font() -> Font {
+        let cell = |x: i32| GlyphDef { rect: Rect { pos: Vec2i { x: 8 * x, y: 0 }, sz: Vec2i { x: 8, y: 8 } }, advance: 6 };
+        let mut font = Font {
+            glyphs: vec![cell(0)],
+            chars: HashMap::new(),
+            ligatures: vec![],
+            pairs: HashMap::new(),
+            themes: vec![],
+            space: 0,
+        };
+        for (i, c) in "abcdefghijklmnopqrstuvwxyz{}:-.!?_".chars().enumerate() {
+            let id = font.add(cell(i as i32 + 1));
+            font.chars.insert(c, id);
+        }
+        font
+    }
 
-            if TSPEED*(x + 19) <= self.cptr { // btm row
-                screen.bitblt(
-                    &self.textset.image, 
-                    self.textset.get_rect(self.rows.get(self.rptr).unwrap()[x-1]), 
-                    Vec2i { x: xpx, y: 88 }
-                );
+    #[test]
+    fn layout_survives_hostile_text() {
+        let font = font();
+        let mut vars = HashMap::new();
+        vars.insert("ME".to_string(), "{ME}{ME} me".to_string());
+        vars.insert("A".to_string(), "{B}".to_string());
+        vars.insert("B".to_string(), "{A}".to_string());
+
+        let max = usize::MAX;
+        let texts = [
+            format!("a{{pause:{}}}b", max),
+            format!("{{speed:{}}}ab", max),
+            format!("{{pause:{}}}{{pause:{}}}a{{pause:{}}}b", max, max, max),
+            format!("{{speed:{}}}a{{pause:{}}}b c. d", max, max),
+            "{pause:99999999999999999999999}a {speed:0}b {speed:-1}c".to_string(),
+            "{".to_string(),
+            "}".to_string(),
+            "a{b".to_string(),
+            "{{}}".to_string(),
+            "{:}{pause:}{color:zzzzzz}{page:1}{}".to_string(),
+            "}{ {page}{page} {color}".to_string(),
+            "{ME} and {A} or {B}".to_string(),
+            "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz".to_string(),
+            "   ".to_string(),
+            String::new(),
+        ];
+        for text in texts.iter() {
+            for width in [0, 8, 160] {
+                for lines in [1, 2] {
+                    let layout = Layout::new(text, &font, &vars, width, lines);
+                    assert_eq!(layout.rows.len(), layout.pages.len() * lines, "{:?} at {}px", text, width);
+                    for (page, rows) in layout.rows.chunks(lines).enumerate() {
+                        for glyph in rows.iter().flatten() {
+                            assert!(glyph.at <= layout.pages[page], "{:?} at {}px", text, width);
+                        }
+                    }
+                }
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn variables_are_not_read_again() {
+        let font = font();
+        let mut vars = HashMap::new();
+        vars.insert("ME".to_string(), "{ME}".to_string());
+        let (glyphs, _) = Layout::line("{ME}", &font, &vars);
+        assert_eq!(glyphs.len(), 4);
+    }
+}
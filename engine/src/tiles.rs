@@ -1,26 +1,187 @@
-use crate::types::{DOWN, UP, LEFT, RIGHT, TILE_SZ};
+use crate::types::{DOWN, UP, LEFT, RIGHT, MOVE_SZ, PPOS, TILE_SZ};
 use crate::types::{Image, Rect, Vec2i};
+use crate::trigger::Triggers;
 
 use core::panic;
+use std::collections::HashMap;
 use std::fs;
+use std::ops::Range;
 use std::rc::Rc;
 
 /// A graphical tile, we'll implement Copy since it's tiny
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 pub struct Tile {
-    pub solid: bool, // ... any extra data like collision flags or other properties
+    pub solid: bool,
+    /// One-way ledge that can only be hopped in this direction
+    pub ledge: Option<usize>,
+    /// Tall grass
+    pub grass: bool,
+    /// Water, can't be walked on
+    pub water: bool,
+    /// A counter that can be talked over
+    pub counter: bool,
+    /// Terrain that takes longer to cross
+    pub slow: bool,
+    /// Ice, keeps you sliding in the direction you entered it
+    pub ice: bool,
+}
+
+impl Tile {
+    pub fn new(solid: bool) -> Self {
+        Self { solid, ..Default::default() }
+    }
+
+    /// Combine two tiles sharing a movement cell
+    fn merge(self, other: Tile) -> Tile {
+        Tile {
+            solid: self.solid || other.solid,
+            ledge: self.ledge.or(other.ledge),
+            grass: self.grass || other.grass,
+            water: self.water || other.water,
+            counter: self.counter || other.counter,
+            slow: self.slow || other.slow,
+            ice: self.ice || other.ice,
+        }
+    }
+}
+
+/// What happens when stepping into a movement cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Can't move there
+    Blocked,
+    /// A normal step
+    Walk,
+    /// A step into tall grass
+    Grass,
+    /// A step that takes longer
+    Slow,
+    /// A step onto ice, keep moving in the same direction
+    Slide,
+    /// Hop the ledge and land on the given cell
+    Jump(Vec2i),
+    /// Water blocks walking
+    Water,
+    /// A counter, talk to whatever is on the given cell behind it
+    TalkOver(Vec2i),
 }
 
 /// A set of tiles used in multiple Tilemaps
 pub struct Tileset {
     pub tiles: Vec<Tile>,
     image: Rc<Image>,
+    /// Animated tiles keyed by the base tile ID placed in maps
+    animations: HashMap<TileID, AnimatedTile>,
+    /// Autotile groups
+    pub autotiles: Vec<Autotile>,
+    /// Payloads fired when the player faces a tile and presses the action key
+    pub interactions: HashMap<usize, Interaction>,
 }
 
 /// Indices into a Tileset
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct TileID(usize);
 
+/// How an autotile picks a variant from its neighbours
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutotileRule {
+    /// 16 variants indexed by which edges match: N = 1, E = 2, S = 4, W = 8
+    Edges16,
+    /// 47 variants for edges and corners, a corner only counts when both edges next to it match.
+    /// Variants are ordered by their mask (N = 1, NE = 2, E = 4, SE = 8, S = 16, SW = 32, W = 64, NW = 128).
+    Blob47,
+}
+
+/// A named terrain (water, path, fence) drawn with whichever variant fits its neighbours
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Autotile {
+    pub name: String,
+    pub rule: AutotileRule,
+    /// One tile ID per variant in the rule's order
+    pub tiles: Vec<usize>,
+}
+
+impl Autotile {
+    pub fn new(name: &str, rule: AutotileRule, tiles: Vec<usize>) -> Self {
+        let count = match rule {
+            AutotileRule::Edges16 => 16,
+            AutotileRule::Blob47 => 47,
+        };
+        assert_eq!(tiles.len(), count, "Autotile {} needs {} tiles", name, count);
+        Self { name: name.to_string(), rule, tiles }
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.tiles.contains(&id)
+    }
+
+    /// Pick a tile given whether the neighbour at each (dx, dy) is in the group
+    pub fn variant(&self, same: impl Fn(i32, i32) -> bool) -> usize {
+        let (n, e, s, w) = (same(0, -1), same(1, 0), same(0, 1), same(-1, 0));
+        match self.rule {
+            AutotileRule::Edges16 => {
+                self.tiles[n as usize | (e as usize) << 1 | (s as usize) << 2 | (w as usize) << 3]
+            },
+            AutotileRule::Blob47 => {
+                let ne = n && e && same(1, -1);
+                let se = s && e && same(1, 1);
+                let sw = s && w && same(-1, 1);
+                let nw = n && w && same(-1, -1);
+                let mask = [n, ne, e, se, s, sw, w, nw]
+                    .iter()
+                    .enumerate()
+                    .fold(0, |m, (i, b)| m | (*b as usize) << i);
+                // Count the valid masks below this one to get its index
+                let idx = (0..mask).filter(|m| Self::blob_valid(*m)).count();
+                self.tiles[idx]
+            },
+        }
+    }
+
+    /// Whether a blob mask only has corners where both edges next to them are set
+    fn blob_valid(m: usize) -> bool {
+        let bit = |i: usize| m & (1 << i) != 0;
+        (!bit(1) || (bit(0) && bit(2))) &&
+        (!bit(3) || (bit(2) && bit(4))) &&
+        (!bit(5) || (bit(4) && bit(6))) &&
+        (!bit(7) || (bit(6) && bit(0)))
+    }
+}
+
+/// A tile that cycles through frames, each shown for a number of ticks
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnimatedTile {
+    pub frames: Vec<TileID>,
+    pub durations: Vec<usize>,
+}
+
+impl AnimatedTile {
+    pub fn new(frames: Vec<usize>, durations: Vec<usize>) -> Self {
+        assert!(!frames.is_empty(), "AnimatedTile needs at least one frame");
+        assert_eq!(frames.len(), durations.len(), "AnimatedTile needs a duration for each frame");
+        Self {
+            frames: frames.into_iter().map(TileID).collect(),
+            durations,
+        }
+    }
+
+    /// Get the frame shown at a given tick, looping forever
+    pub fn frame_at(&self, tick: usize) -> TileID {
+        let total: usize = self.durations.iter().sum();
+        if total == 0 {
+            return self.frames[0];
+        }
+        let mut t = tick % total;
+        for (frame, dur) in self.frames.iter().zip(self.durations.iter()) {
+            if t < *dur {
+                return *frame;
+            }
+            t -= dur;
+        }
+        self.frames[self.frames.len() - 1]
+    }
+}
+
 /// Grab a tile with a given ID
 impl std::ops::Index<TileID> for Tileset {
     type Output = Tile;
@@ -34,6 +195,182 @@ impl Tileset {
         Self {
             tiles,
             image: texture,
+            animations: HashMap::new(),
+            autotiles: vec![],
+            interactions: HashMap::new(),
+        }
+    }
+
+    /// Load tile definitions from a JSON sidecar next to the tilesheet.
+    /// Every tile is solid unless listed in "walkable", other behaviours are optional lists
+    /// ("grass", "water", "counter", "slow", "ice") and a "ledges" map of ID to direction.
+    /// Autotile groups can be listed under "autotiles" and an "interactions" map of ID to
+    /// {"text": key} or {"script": id} makes tiles respond to the action key.
+    pub fn from_json(texture: Rc<Image>, path: &std::path::Path) -> Self {
+        Self::try_from_json(texture, path).unwrap_or_else(|error| panic!("Problem reading {:?}: {}", path, error))
+    }
+
+    /// Like from_json, returning what's wrong with the file instead of panicking
+    pub fn try_from_json(texture: Rc<Image>, path: &std::path::Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let defs: serde_json::Value = serde_json::from_str(&content).map_err(|error| error.to_string())?;
+        let ids = |key: &str| -> Result<Vec<usize>, String> {
+            match defs.get(key) {
+                Some(v) => v
+                    .as_array()
+                    .ok_or(format!("\"{}\" must be a list", key))?
+                    .iter()
+                    .map(|x| x.as_u64().map(|id| id as usize).ok_or(format!("\"{}\" must hold tile IDs", key)))
+                    .collect(),
+                None => Ok(vec![]),
+            }
+        };
+
+        let count = defs["count"].as_u64().ok_or("needs a tile count")? as usize;
+        let mut tiles = vec![Tile::new(true); count];
+        let mut set = |key: &str, f: fn(&mut Tile)| -> Result<(), String> {
+            for id in ids(key)? {
+                if id >= count {
+                    return Err(format!("\"{}\" lists tile {} but only has {} tiles", key, id, count));
+                }
+                f(&mut tiles[id]);
+            }
+            Ok(())
+        };
+        set("walkable", |t| t.solid = false)?;
+        set("grass", |t| t.grass = true)?;
+        set("water", |t| t.water = true)?;
+        set("counter", |t| t.counter = true)?;
+        set("slow", |t| t.slow = true)?;
+        set("ice", |t| t.ice = true)?;
+
+        if let Some(ledges) = defs.get("ledges").and_then(|l| l.as_object()) {
+            for (id, dir) in ledges {
+                let id = id.parse::<usize>().map_err(|_| format!("ledge \"{}\" must be a tile ID", id))?;
+                if id >= count {
+                    return Err(format!("ledge {} but only has {} tiles", id, count));
+                }
+                tiles[id].ledge = Some(parse_dir(dir).ok_or(format!("invalid ledge direction for tile {}", id))?);
+            }
+        }
+
+        let mut tileset = Self::new(tiles, texture);
+        if let Some(autotiles) = defs.get("autotiles") {
+            tileset.parse_autotiles(autotiles)?;
+        }
+        if let Some(interactions) = defs.get("interactions").and_then(|i| i.as_object()) {
+            for (id, def) in interactions {
+                let id = id.parse::<usize>().map_err(|_| format!("interaction \"{}\" must be a tile ID", id))?;
+                if id >= count {
+                    return Err(format!("interaction {} but only has {} tiles", id, count));
+                }
+                let interaction = parse_interaction(def).ok_or(format!("invalid interaction for tile {}", id))?;
+                tileset.interactions.insert(id, interaction);
+            }
+        }
+        Ok(tileset)
+    }
+
+    /// Every (tile position, ID) in a map that has no definition in this tileset
+    pub fn undefined_tiles(&self, w: usize, map: &[usize]) -> Vec<(Vec2i, usize)> {
+        map.iter()
+            .enumerate()
+            .filter(|(_, id)| **id >= self.tiles.len())
+            .map(|(i, id)| (Vec2i { x: (i % w) as i32, y: (i / w) as i32 }, *id))
+            .collect()
+    }
+
+    /// Make every occurrence of base in a map animate
+    pub fn add_animation(&mut self, base: usize, anim: AnimatedTile) {
+        self.animations.insert(TileID(base), anim);
+    }
+
+    /// Load animated tiles from a JSON list of { "base", "frames", "durations" } objects
+    pub fn load_animations(&mut self, path: &std::path::Path) {
+        let content = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(error) => panic!("Problem reading file: {:?}", error),
+        };
+        let defs: serde_json::Value = serde_json::from_str(&content).unwrap();
+        let ids = |v: &serde_json::Value| -> Vec<usize> {
+            v.as_array()
+                .expect("Animated tile fields must be lists")
+                .iter()
+                .map(|x| x.as_u64().expect("Animated tile fields must be numbers") as usize)
+                .collect()
+        };
+
+        for def in defs.as_array().expect("Animated tiles must be a list") {
+            let base = def["base"].as_u64().expect("Animated tile needs a base") as usize;
+            let frames = ids(&def["frames"]);
+            for id in frames.iter().chain([&base]) {
+                assert!(*id < self.tiles.len(), "Animated tile {} uses tile {} but only has {} tiles", base, id, self.tiles.len());
+            }
+            self.add_animation(base, AnimatedTile::new(frames, ids(&def["durations"])));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Draw a single tile, e.g. for a palette
+    pub fn draw_tile(&self, screen: &mut Image, id: usize, to: Vec2i) {
+        screen.bitblt(&self.image, self.get_rect(TileID(id)), to);
+    }
+
+    pub fn is_animated(&self, id: TileID) -> bool {
+        self.animations.contains_key(&id)
+    }
+
+    /// Load autotiles from a JSON list of { "name", "rule": "edges16" | "blob47", "tiles" } objects,
+    /// the same list can also go under "autotiles" in the tileset's sidecar
+    pub fn load_autotiles(&mut self, path: &std::path::Path) {
+        let content = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(error) => panic!("Problem reading file: {:?}", error),
+        };
+        let defs: serde_json::Value = serde_json::from_str(&content).unwrap();
+        if let Err(error) = self.parse_autotiles(&defs) {
+            panic!("Problem reading {:?}: {}", path, error);
+        }
+    }
+
+    fn parse_autotiles(&mut self, defs: &serde_json::Value) -> Result<(), String> {
+        for def in defs.as_array().ok_or("autotiles must be a list")? {
+            let name = def["name"].as_str().ok_or("autotile needs a name")?;
+            let rule = match def["rule"].as_str() {
+                Some("edges16") => AutotileRule::Edges16,
+                Some("blob47") => AutotileRule::Blob47,
+                _ => return Err(format!("invalid rule for autotile {}", name)),
+            };
+            let tiles = def["tiles"]
+                .as_array()
+                .ok_or(format!("autotile {} tiles must be a list", name))?
+                .iter()
+                .map(|x| x.as_u64().map(|id| id as usize).ok_or(format!("autotile {} tiles must be numbers", name)))
+                .collect::<Result<Vec<usize>, String>>()?;
+            if let Some(id) = tiles.iter().find(|id| **id >= self.tiles.len()) {
+                return Err(format!("autotile {} uses tile {} but only has {} tiles", name, id, self.tiles.len()));
+            }
+            self.autotiles.push(Autotile::new(name, rule, tiles));
+        }
+        Ok(())
+    }
+
+    pub fn autotile(&self, name: &str) -> Option<&Autotile> {
+        self.autotiles.iter().find(|a| a.name == name)
+    }
+
+    /// Get the tile to draw in place of id at a given tick
+    pub fn frame(&self, id: TileID, tick: usize) -> TileID {
+        match self.animations.get(&id) {
+            Some(anim) => anim.frame_at(tick),
+            None => id,
         }
     }
     /// Get the frame rect for a tile ID
@@ -56,32 +393,558 @@ impl Tileset {
     }
 }
 
+/// A shape used to reveal a MapMask, measured in tiles
+#[derive(Clone, Debug, PartialEq)]
+pub enum Brush {
+    /// A circle with the given radius
+    Circle(f32),
+    /// A square reaching the given distance from its center
+    Square(f32),
+    /// A w by h grid of reveal amounts (255 fully reveals) centered on the brush
+    Bitmap { w: usize, h: usize, cells: Vec<u8> },
+}
+
+impl Brush {
+    /// How far the brush reaches from its center in x and y
+    fn extent(&self) -> (f32, f32) {
+        match self {
+            Brush::Circle(r) | Brush::Square(r) => (*r, *r),
+            Brush::Bitmap { w, h, .. } => (*w as f32 / 2.0, *h as f32 / 2.0),
+        }
+    }
+
+    /// How much of a tile at (dx, dy) from the center gets revealed, from 0.0 to 1.0.
+    /// The last `soft` tiles inside the edge fade out instead of stopping sharply.
+    fn amount(&self, dx: f32, dy: f32, soft: f32) -> f32 {
+        let edge = |d: f32, r: f32| {
+            if d > r {
+                0.0
+            } else if d <= r - soft {
+                1.0
+            } else {
+                (r - d) / soft
+            }
+        };
+        match self {
+            Brush::Circle(r) => edge((dx * dx + dy * dy).sqrt(), *r),
+            Brush::Square(r) => edge(dx.abs().max(dy.abs()), *r),
+            Brush::Bitmap { w, h, cells } => {
+                let x = (dx + *w as f32 / 2.0).floor();
+                let y = (dy + *h as f32 / 2.0).floor();
+                if x < 0.0 || y < 0.0 || x as usize >= *w || y as usize >= *h {
+                    0.0
+                } else {
+                    cells[y as usize * w + x as usize] as f32 / 255.0
+                }
+            }
+        }
+    }
+}
+
+/// How much of a Tilemap still covers whatever is under it, per tile from 255 (covered) to 0 (revealed)
 #[derive(PartialEq, Eq, Clone, Hash, Debug)]
 pub struct MapMask {
-    pub mask: Box<[bool]>,
+    pub mask: Box<[u8]>,
     pub dims: (usize, usize),
+    /// Number of fully revealed tiles
     pub swapc: usize
 }
 
 impl MapMask {
     pub fn new(dims: (usize, usize)) -> Self {
         Self {
-            mask: vec![true; dims.0 * dims.1].into_boxed_slice(),
+            mask: vec![255; dims.0 * dims.1].into_boxed_slice(),
             dims,
             swapc: 0
         }
     }
 
     pub fn unmask(&mut self, x: usize, y: usize) {
-        if self.mask[y * self.dims.0 + x] {
-            self.mask[y * self.dims.0 + x] = false;
-            self.swapc += 1;
+        self.reveal(x, y, 1.0);
+    }
+
+    /// Reveal a tile by amount from 0.0 to 1.0, a tile never gets covered back up
+    pub fn reveal(&mut self, x: usize, y: usize, amount: f32) {
+        let to = ((1.0 - amount.clamp(0.0, 1.0)) * 255.0).round() as u8;
+        let cell = &mut self.mask[y * self.dims.0 + x];
+        if to < *cell {
+            *cell = to;
+            if to == 0 {
+                self.swapc += 1;
+            }
         }
     }
 
+    /// Reveal with a brush centered on a point in tile units, tiles off the map are ignored
+    pub fn paint(&mut self, (cx, cy): (f32, f32), brush: &Brush, soft: f32) {
+        let (ex, ey) = brush.extent();
+        let x0 = (cx - ex).floor().max(0.0) as usize;
+        let y0 = (cy - ey).floor().max(0.0) as usize;
+        let x1 = ((cx + ex).ceil().max(0.0) as usize).min(self.dims.0);
+        let y1 = ((cy + ey).ceil().max(0.0) as usize).min(self.dims.1);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // Measure from the center of each tile
+                let amount = brush.amount(x as f32 + 0.5 - cx, y as f32 + 0.5 - cy, soft);
+                if amount > 0.0 {
+                    self.reveal(x, y, amount);
+                }
+            }
+        }
+    }
+
+    /// Whether a tile is still at least partly covered
     pub fn at(&self, x: usize, y: usize) -> bool {
+        self.mask[y * self.dims.0 + x] > 0
+    }
+
+    pub fn coverage(&self, x: usize, y: usize) -> u8 {
         self.mask[y * self.dims.0 + x]
     }
+
+    /// Fraction of the map that has been revealed, from 0.0 to 1.0
+    pub fn revealed(&self) -> f32 {
+        let covered: usize = self.mask.iter().map(|c| *c as usize).sum();
+        1.0 - covered as f32 / (255 * self.mask.len()) as f32
+    }
+}
+
+/// Draw under, then over on top of it wherever over's mask still covers it
+pub fn composite(under: &Tilemap, over: &Tilemap, screen: &mut Image) {
+    under.draw(screen);
+    over.masked_draw(screen);
+}
+
+/// The layers above sprites to go with composite, under's only show where over has been revealed
+pub fn composite_above(under: &Tilemap, over: &Tilemap, screen: &mut Image) {
+    under.revealed_draw_above(screen, &over.mask);
+    over.masked_draw_above(screen);
+}
+
+/// Which tiles of a draw show, and how much
+#[derive(Clone, Copy)]
+enum Masking<'a> {
+    None,
+    /// As much as the mask still covers
+    Covered(&'a MapMask),
+    /// As much as the mask has revealed
+    Revealed(&'a MapMask),
+}
+
+impl Masking<'_> {
+    fn coverage(self, x: usize, y: usize) -> u8 {
+        match self {
+            Masking::None => 255,
+            Masking::Covered(mask) => mask.coverage(x, y),
+            // Tiles the mask doesn't reach haven't been revealed
+            Masking::Revealed(mask) if x >= mask.dims.0 || y >= mask.dims.1 => 0,
+            Masking::Revealed(mask) => 255 - mask.coverage(x, y),
+        }
+    }
+}
+
+/// Read a direction name ("down", "up", "left", "right") from JSON
+pub fn parse_dir(v: &serde_json::Value) -> Option<usize> {
+    match v.as_str() {
+        Some("down") => Some(DOWN),
+        Some("up") => Some(UP),
+        Some("left") => Some(LEFT),
+        Some("right") => Some(RIGHT),
+        _ => None
+    }
+}
+
+/// Read an [x, y] pair from JSON
+pub fn parse_pos(v: &serde_json::Value) -> Option<Vec2i> {
+    let xy = v.as_array()?;
+    Some(Vec2i {
+        x: xy.first()?.as_i64()? as i32,
+        y: xy.get(1)?.as_i64()? as i32,
+    })
+}
+
+/// Read {"text": key} or {"script": id} from JSON
+fn parse_interaction(v: &serde_json::Value) -> Option<Interaction> {
+    if let Some(key) = v.get("text").and_then(|k| k.as_str()) {
+        Some(Interaction::Text(key.to_string()))
+    } else {
+        v.get("script").and_then(|id| id.as_str()).map(|id| Interaction::Script(id.to_string()))
+    }
+}
+
+/// What happens when the player faces something and presses the action key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Interaction {
+    /// Show the dialogue with this key
+    Text(String),
+    /// Run the game's script with this id
+    Script(String),
+}
+
+/// Something placed on a movement cell that can be interacted with, like a sign or a PC
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapObject {
+    /// Movement cell the object sits on
+    pub pos: Vec2i,
+    pub interaction: Interaction,
+    /// Whether the object blocks walking onto its cell
+    pub solid: bool,
+}
+
+/// A movement cell that sends the player to another map
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warp {
+    /// Movement cell the warp sits on
+    pub pos: Vec2i,
+    /// Name of the destination map in a MapLoader
+    pub dest: String,
+    /// Movement cell the player arrives on
+    pub dest_pos: Vec2i,
+    /// Direction the player faces on arrival
+    pub dir: usize,
+}
+
+/// An edge of a map joined to another map, so walking off one walks onto the other
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Connection {
+    /// Which edge, as the direction you walk to cross it (DOWN is the south edge)
+    pub dir: usize,
+    /// Name of the neighbouring map in a MapLoader
+    pub dest: String,
+    /// How far the neighbour is shifted along the edge, in movement cells
+    pub offset: i32,
+}
+
+/// A ground tile replaced while the game runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileChange {
+    /// Tile position on the map
+    pub pos: Vec2i,
+    /// ID now at pos
+    pub id: usize,
+}
+
+/// Builds maps by name, so warps can load their destination
+#[derive(Default)]
+pub struct MapLoader {
+    loaders: HashMap<String, fn() -> Tilemap>,
+    /// Tiles changed on maps that have been unloaded, put back when they load again
+    changes: HashMap<String, Vec<TileChange>>,
+}
+
+impl MapLoader {
+    pub fn new() -> Self {
+        Self { loaders: HashMap::new(), changes: HashMap::new() }
+    }
+
+    pub fn add(&mut self, name: &str, loader: fn() -> Tilemap) {
+        self.loaders.insert(name.to_string(), loader);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.loaders.contains_key(name)
+    }
+
+    /// Build a map, with the tiles changed on it before it was last unloaded
+    pub fn load(&self, name: &str) -> Tilemap {
+        let mut map = match self.loaders.get(name) {
+            Some(loader) => loader(),
+            None => panic!("No map named {}", name),
+        };
+        if let Some(changes) = self.changes.get(name) {
+            map.apply_changes(changes);
+        }
+        map
+    }
+
+    /// Keep the changes of a map that's being dropped for the next time it loads
+    pub fn unload(&mut self, name: &str, map: &Tilemap) {
+        if !map.changes().is_empty() {
+            self.changes.insert(name.to_string(), map.changes().to_vec());
+        }
+    }
+}
+
+/// A fade out to black, then a fade back in, with a warp taken while the screen is dark
+pub struct Transition {
+    warp: Option<Warp>,
+    t: i32,
+    len: i32,
+}
+
+impl Transition {
+    /// len is the number of frames for each half of the fade
+    pub fn new(len: i32) -> Self {
+        Self { warp: None, t: 0, len }
+    }
+
+    pub fn start(&mut self, warp: Warp) {
+        self.warp = Some(warp);
+        self.t = 1;
+    }
+
+    pub fn active(&self) -> bool {
+        self.t > 0
+    }
+
+    /// Advance the fade, returns the warp on the frame the screen is fully dark
+    pub fn tick(&mut self) -> Option<Warp> {
+        if !self.active() {
+            return None;
+        }
+        self.t += 1;
+        if self.t >= 2 * self.len {
+            self.t = 0;
+        }
+        if self.t == self.len {
+            self.warp.take()
+        } else {
+            None
+        }
+    }
+
+    pub fn draw(&self, screen: &mut Image) {
+        if self.active() {
+            let dark = if self.t <= self.len { self.t } else { 2 * self.len - self.t };
+            screen.darken(dark as f32 / self.len as f32);
+        }
+    }
+}
+
+/// The active map plus the connected maps near enough to the player to be seen.
+/// Positions are in the active map's movement cells.
+pub struct ConnectedMaps {
+    loader: MapLoader,
+    /// Name of the active map
+    pub name: String,
+    pub active: Tilemap,
+    /// Loaded neighbours with their top left movement cell
+    neighbours: HashMap<String, (Vec2i, Tilemap)>,
+    /// How close to an edge, in movement cells, before the map across it is loaded
+    pub range: i32,
+}
+
+impl ConnectedMaps {
+    pub fn new(loader: MapLoader, name: &str, range: i32) -> Self {
+        Self {
+            active: loader.load(name),
+            loader,
+            name: name.to_string(),
+            neighbours: HashMap::new(),
+            range,
+        }
+    }
+
+    /// Where a neighbour of the active map starts, in the active map's movement cells
+    fn origin(&self, conn: &Connection, neighbour: &Tilemap) -> Vec2i {
+        let (w, h) = self.active.move_dims();
+        let (nw, nh) = neighbour.move_dims();
+        match conn.dir {
+            DOWN => Vec2i { x: conn.offset, y: h },
+            UP => Vec2i { x: conn.offset, y: -nh },
+            LEFT => Vec2i { x: -nw, y: conn.offset },
+            RIGHT => Vec2i { x: w, y: conn.offset },
+            _ => panic!("Invalid direction")
+        }
+    }
+
+    /// Load the neighbours across edges the player is near and drop the rest
+    pub fn stream(&mut self, pos: Vec2i) {
+        let (w, h) = self.active.move_dims();
+        let mut near: HashMap<String, (Vec2i, Tilemap)> = HashMap::new();
+
+        for conn in self.active.connections.clone() {
+            let dist = match conn.dir {
+                DOWN => h - 1 - pos.y,
+                UP => pos.y,
+                LEFT => pos.x,
+                RIGHT => w - 1 - pos.x,
+                _ => panic!("Invalid direction")
+            };
+            if dist > self.range {
+                continue;
+            }
+            let map = match self.neighbours.remove(&conn.dest) {
+                Some((_, map)) => map,
+                None => self.loader.load(&conn.dest),
+            };
+            near.insert(conn.dest.clone(), (self.origin(&conn, &map), map));
+        }
+        for (name, (_, map)) in std::mem::replace(&mut self.neighbours, near) {
+            self.loader.unload(&name, &map);
+        }
+    }
+
+    /// Build a map through the loader without making it part of the world
+    pub fn load(&self, name: &str) -> Tilemap {
+        self.loader.load(name)
+    }
+
+    /// Swap in a different active map, dropping the old one and its neighbours.
+    /// Call stream afterwards to load the new map's neighbours.
+    pub fn set_active(&mut self, name: &str, map: Tilemap) {
+        for (name, (_, map)) in self.neighbours.drain() {
+            self.loader.unload(&name, &map);
+        }
+        let old = std::mem::replace(&mut self.active, map);
+        let old_name = std::mem::replace(&mut self.name, name.to_string());
+        self.loader.unload(&old_name, &old);
+    }
+
+    pub fn loaded(&self) -> impl Iterator<Item = &str> {
+        self.neighbours.keys().map(|k| k.as_str())
+    }
+
+    /// The map a position falls on, with the position in that map's movement cells
+    fn map_at(&self, pos: Vec2i) -> Option<(&Tilemap, Vec2i)> {
+        if self.active.in_bounds(pos) {
+            return Some((&self.active, pos));
+        }
+        self.neighbours
+            .values()
+            .map(|(origin, map)| (map, pos - *origin))
+            .find(|(map, local)| map.in_bounds(*local))
+    }
+
+    /// What happens stepping from pos in dir, across seams too
+    pub fn step(&self, from: Vec2i, dir: usize) -> Step {
+        let mut to = from;
+        to.walk(dir);
+        let Some((map, local)) = self.map_at(to) else { return Step::Blocked };
+
+        // Step from the cell before, in the target map's cells, and report back in the active map's
+        let mut back = local;
+        back.walk(match dir { DOWN => UP, UP => DOWN, LEFT => RIGHT, _ => LEFT });
+        let shift = to - local;
+        let can_land = |land: Vec2i| self.map_at(land + shift).is_some_and(|(map, land)| map.can_move_to(land));
+        match map.step_landing(back, dir, can_land) {
+            Step::Jump(land) => Step::Jump(land + shift),
+            Step::TalkOver(behind) => Step::TalkOver(behind + shift),
+            step => step,
+        }
+    }
+
+    /// Once the player has walked onto a neighbour, make it the active map.
+    /// Returns the player's position in the new map's cells.
+    pub fn cross(&mut self, pos: Vec2i) -> Vec2i {
+        if self.active.in_bounds(pos) {
+            return pos;
+        }
+        let Some(name) = self.neighbours
+            .iter()
+            .find(|(_, (origin, map))| map.in_bounds(pos - *origin))
+            .map(|(name, _)| name.clone()) else { return pos };
+
+        let (origin, mut map) = self.neighbours.remove(&name).unwrap();
+        map.position = self.active.position + Vec2i { x: MOVE_SZ * origin.x, y: MOVE_SZ * origin.y };
+        let old = std::mem::replace(&mut self.active, map);
+        let old_name = std::mem::replace(&mut self.name, name);
+
+        // Keep the map we came from around, it's likely still in range
+        let back = self.active.connections.iter().find(|c| c.dest == old_name).cloned();
+        for (name, (_, map)) in self.neighbours.drain() {
+            self.loader.unload(&name, &map);
+        }
+        match back {
+            Some(conn) => {
+                let origin = self.origin(&conn, &old);
+                self.neighbours.insert(old_name, (origin, old));
+            },
+            None => self.loader.unload(&old_name, &old),
+        }
+
+        let pos = pos - origin;
+        self.stream(pos);
+        pos
+    }
+
+    pub fn translate(&mut self, dir: usize) {
+        self.active.translate(dir);
+    }
+
+    pub fn set_tick(&mut self, tick: usize) {
+        self.active.set_tick(tick);
+        for (_, map) in self.neighbours.values_mut() {
+            map.set_tick(tick);
+        }
+    }
+
+    /// Line the neighbours up with the active map
+    fn place(&mut self) {
+        let base = self.active.position;
+        for (origin, map) in self.neighbours.values_mut() {
+            map.position = base + Vec2i { x: MOVE_SZ * origin.x, y: MOVE_SZ * origin.y };
+        }
+    }
+
+    pub fn draw(&mut self, screen: &mut Image) {
+        self.place();
+        for (_, map) in self.neighbours.values() {
+            map.draw(screen);
+        }
+        self.active.draw(screen);
+    }
+
+    pub fn draw_above(&mut self, screen: &mut Image) {
+        self.place();
+        for (_, map) in self.neighbours.values() {
+            map.draw_above(screen);
+        }
+        self.active.draw_above(screen);
+    }
+}
+
+/// A single grid of tiles in a Tilemap, drawn either below or above sprites
+pub struct TileLayer {
+    /// A row-major grid of tile IDs in tileset, None where the layer is empty
+    map: Vec<Option<TileID>>,
+    /// Whether the layer is drawn after entities (tree tops, roofs, tall grass)
+    pub above: bool,
+}
+
+impl TileLayer {
+    pub fn new(map: Vec<Option<usize>>, above: bool) -> Self {
+        Self {
+            map: map.into_iter().map(|id| id.map(TileID)).collect(),
+            above,
+        }
+    }
+
+    /// Read a layer from a CSV, negative cells (e.g. -1) are left empty
+    pub fn from_csv(path: &std::path::Path, above: bool) -> Self {
+        Self::new(Self::read_csv(path), above)
+    }
+
+    fn read_csv(path: &std::path::Path) -> Vec<Option<usize>> {
+        let content = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(error) => panic!("Problem reading file: {:?}", error),
+        };
+
+        content
+            .split([',', '\n'])
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.parse::<i64>().unwrap())
+            .map(|x| if x < 0 { None } else { Some(x as usize) })
+            .collect()
+    }
+
+    pub fn at(&self, idx: usize) -> Option<TileID> {
+        self.map[idx]
+    }
+}
+
+/// Pre-rendered square chunks of a Tilemap, one image each for the layers below and above sprites
+struct ChunkCache {
+    /// Chunk width and height in tiles
+    size: usize,
+    /// Number of chunks across
+    cols: usize,
+    /// None where a chunk holds animated tiles and has to be drawn tile by tile
+    below: Vec<Option<Image>>,
+    above: Vec<Option<Image>>,
 }
 
 /// An actual tilemap
@@ -92,14 +955,30 @@ pub struct Tilemap {
     pub dims: (usize, usize),
     /// Which tileset is used for this tilemap
     tileset: Rc<Tileset>,
-    /// A row-major grid of tile IDs in tileset
-    map: Vec<TileID>,
+    /// Ordered tile layers, the first one is the ground and must be fully filled
+    layers: Vec<TileLayer>,
     /// Scale factor
     sf: i32,
     /// Vector containing which tiles are solid
     movemap: Vec<bool>,
+    /// Tile properties combined for each movement cell
+    cellmap: Vec<Tile>,
     pub mask: MapMask,
-    pub swapc: usize
+    pub swapc: usize,
+    /// Global tick used to pick animated tile frames
+    tick: usize,
+    /// Warps to other maps
+    warps: Vec<Warp>,
+    /// Edges joined to other maps
+    pub connections: Vec<Connection>,
+    /// Ground tiles replaced since loading, at most one per position
+    changes: Vec<TileChange>,
+    /// Interactable objects on movement cells
+    objects: Vec<MapObject>,
+    /// Zones that report actors walking in and out
+    pub triggers: Triggers,
+    /// Optional pre-rendered chunks
+    cache: Option<ChunkCache>,
 }
 
 impl Tilemap {
@@ -109,22 +988,31 @@ impl Tilemap {
         tileset: Rc<Tileset>,
         map: Vec<usize>,
         sf: i32,
-        moveables: Vec<usize>
     ) -> Self {
         assert_eq!(dims.0 * dims.1, map.len(), "Tilemap is the wrong size!");
+        Self::check_defined(dims, &map, &tileset);
 
-        let movemap = Self::move_map(dims, &map, moveables, sf);
+        let movemap = Self::move_map(dims, &map, &tileset, sf);
+        let cellmap = Self::cell_map(dims, &map, &tileset, sf);
         let mask = MapMask::new(dims);
 
         Self {
             position,
             dims,
             tileset,
-            map: map.into_iter().map(TileID).collect(),
+            layers: vec![TileLayer::new(map.into_iter().map(Some).collect(), false)],
             sf,
             movemap,
+            cellmap,
             mask,
-            swapc: 0
+            swapc: 0,
+            tick: 0,
+            warps: vec![],
+            connections: vec![],
+            changes: vec![],
+            objects: vec![],
+            triggers: Triggers::new(),
+            cache: None,
         }
     }
 
@@ -134,41 +1022,274 @@ impl Tilemap {
         tileset: Rc<Tileset>,
         path: &std::path::Path,
         sf: i32,
-        moveables: Vec<usize>
     ) -> Self {
+        let map: Vec<usize> = TileLayer::read_csv(path)
+            .into_iter()
+            .map(|x| x.expect("Ground layer can't have empty tiles"))
+            .collect();
+        Self::new(position, dims, tileset, map, sf)
+    }
+
+    /// The variant of group that fits tile pos on the ground layer, neighbours off the map count as matching
+    pub fn autotile_at(&self, pos: Vec2i, group: &Autotile) -> usize {
+        let ground = &self.layers[0].map;
+        Self::resolve_autotile(|i| ground[i].unwrap().0, self.dims, pos, group)
+    }
+
+    /// The variant of group for pos, reading the ID at each row-major index with id_at
+    fn resolve_autotile(id_at: impl Fn(usize) -> usize, (w, h): (usize, usize), pos: Vec2i, group: &Autotile) -> usize {
+        group.variant(|dx, dy| {
+            let (x, y) = (pos.x + dx, pos.y + dy);
+            if x < 0 || y < 0 || x >= w as i32 || y >= h as i32 {
+                return true;
+            }
+            group.contains(id_at(y as usize * w + x as usize))
+        })
+    }
+
+    /// Paint group onto tile pos of a row-major map of IDs and fix up the variants around it
+    pub fn paint_autotile(map: &mut [usize], (w, h): (usize, usize), pos: Vec2i, group: &Autotile) {
+        map[pos.y as usize * w + pos.x as usize] = group.tiles[0];
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let p = Vec2i { x: pos.x + dx, y: pos.y + dy };
+                if p.x < 0 || p.y < 0 || p.x >= w as i32 || p.y >= h as i32 {
+                    continue;
+                }
+                let i = p.y as usize * w + p.x as usize;
+                if group.contains(map[i]) {
+                    map[i] = Self::resolve_autotile(|j| map[j], (w, h), p, group);
+                }
+            }
+        }
+    }
+
+    /// Tile IDs of the ground layer, row-major
+    pub fn ground_ids(&self) -> Vec<usize> {
+        self.layers[0].map.iter().map(|id| id.unwrap().0).collect()
+    }
+
+    /// Write the ground layer back out in the format from_csv reads
+    pub fn save_csv(&self, path: &std::path::Path) {
+        let content: String = self.layers[0]
+            .map
+            .chunks_exact(self.dims.0)
+            .map(|row| {
+                row.iter()
+                    .map(|id| id.map_or(-1, |id| id.0 as i64).to_string())
+                    .collect::<Vec<String>>()
+                    .join(",") + "\n"
+            })
+            .collect();
+        if let Err(error) = fs::write(path, content) {
+            panic!("Problem writing file: {:?}", error);
+        }
+    }
+
+    /// Replace a ground tile, updating its movement cell and cached chunk
+    pub fn set_tile(&mut self, pos: Vec2i, id: usize) {
+        assert!(
+            pos.x >= 0 && pos.y >= 0 && (pos.x as usize) < self.dims.0 && (pos.y as usize) < self.dims.1,
+            "Tile ({}, {}) is off the map", pos.x, pos.y
+        );
+        assert!(id < self.tileset.len(), "Tile {} has no definition in the tileset", id);
+
+        self.layers[0].map[pos.y as usize * self.dims.0 + pos.x as usize] = Some(TileID(id));
+        match self.changes.iter_mut().find(|c| c.pos == pos) {
+            Some(change) => change.id = id,
+            None => self.changes.push(TileChange { pos, id }),
+        }
+
+        // Only the movement cell holding the tile can change, the odd row and column of a map with odd dims have none
+        let sf = self.sf as usize;
+        let (cx, cy) = (pos.x as usize / sf, pos.y as usize / sf);
+        if cx < self.dims.0 / sf && cy < self.dims.1 / sf {
+            let mut cell = Tile::default();
+            for row in sf*cy..(sf*(cy+1)).min(self.dims.1) {
+                for col in sf*cx..(sf*(cx+1)).min(self.dims.0) {
+                    cell = cell.merge(self.tileset[self.layers[0].map[row * self.dims.0 + col].unwrap()]);
+                }
+            }
+            let ci = cy * (self.dims.0 / sf) + cx;
+            self.movemap[ci] = !cell.solid;
+            self.cellmap[ci] = cell;
+        }
+
+        self.invalidate(pos);
+    }
+
+    /// Every ground tile replaced since the map was loaded
+    pub fn changes(&self) -> &[TileChange] {
+        &self.changes
+    }
+
+    /// Replay changes on top of the loaded map
+    pub fn apply_changes(&mut self, changes: &[TileChange]) {
+        for change in changes {
+            self.set_tile(change.pos, change.id);
+        }
+    }
+
+    /// Write changes as a list of [x, y, id]
+    pub fn save_changes(&self, path: &std::path::Path) {
+        let content = serde_json::Value::Array(
+            self.changes.iter()
+                .map(|c| serde_json::json!([c.pos.x, c.pos.y, c.id]))
+                .collect()
+        );
+        if let Err(error) = fs::write(path, content.to_string()) {
+            panic!("Problem writing file: {:?}", error);
+        }
+    }
+
+    /// Replay changes written by save_changes
+    pub fn load_changes(&mut self, path: &std::path::Path) {
         let content = match fs::read_to_string(path) {
             Ok(s) => s,
             Err(error) => panic!("Problem reading file: {:?}", error),
         };
+        let defs: serde_json::Value = serde_json::from_str(&content).unwrap();
 
-        let map: Vec<usize> = content
-            .split([',', '\n'])
-            .collect::<Vec<&str>>()
-            .into_iter()
-            .filter(|x| !x.is_empty())
-            .map(|x| x.parse::<usize>().unwrap())
-            .collect();
+        for def in defs.as_array().expect("Tile changes must be a list") {
+            let pos = parse_pos(def).expect("Tile change needs an x and y");
+            let id = def.get(2).and_then(|id| id.as_u64()).expect("Tile change needs an ID") as usize;
+            self.set_tile(pos, id);
+        }
+    }
 
-        let movemap = Self::move_map(dims, &map, moveables, sf);
-        let mask = MapMask::new(dims);
+    /// Push a layer on top of the existing ones
+    pub fn add_layer(&mut self, layer: TileLayer) {
+        assert_eq!(self.dims.0 * self.dims.1, layer.map.len(), "TileLayer is the wrong size!");
+        self.layers.push(layer);
+        self.invalidate_all();
+    }
 
-        assert_eq!(dims.0 * dims.1, map.len(), "Tilemap is the wrong size!");
-        Self {
-            position,
-            dims,
-            tileset,
-            map: map.into_iter().map(TileID).collect(),
-            sf,
-            movemap,
-            mask,
-            swapc: 0
+    pub fn layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    pub fn add_warp(&mut self, warp: Warp) {
+        self.warps.push(warp);
+    }
+
+    /// Load warps from a JSON list of { "pos": [x, y], "dest", "dest_pos": [x, y], "dir" } objects
+    pub fn load_warps(&mut self, path: &std::path::Path) {
+        let content = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(error) => panic!("Problem reading file: {:?}", error),
+        };
+        let defs: serde_json::Value = serde_json::from_str(&content).unwrap();
+
+        for def in defs.as_array().expect("Warps must be a list") {
+            self.add_warp(Warp {
+                pos: parse_pos(&def["pos"]).expect("Warp needs a pos"),
+                dest: def["dest"].as_str().expect("Warp needs a dest").to_string(),
+                dest_pos: parse_pos(&def["dest_pos"]).expect("Warp needs a dest_pos"),
+                dir: parse_dir(&def["dir"]).expect("Warp needs a dir"),
+            });
         }
     }
 
-    pub fn contains_solid((w, _h): (usize, usize), map: &[usize], Rect { pos, sz }: Rect, moveables: Vec<usize>) -> bool {
+    pub fn warps(&self) -> &[Warp] {
+        &self.warps
+    }
+
+    pub fn warp_at(&self, pos: Vec2i) -> Option<&Warp> {
+        self.warps.iter().find(|w| w.pos == pos)
+    }
+
+    /// Replace the map's zones with those in a file, see Triggers::from_json
+    pub fn load_triggers(&mut self, path: &std::path::Path) {
+        self.triggers = Triggers::from_json(path);
+    }
+
+    pub fn add_object(&mut self, object: MapObject) {
+        self.objects.push(object);
+    }
+
+    /// Reads a list of {pos, text | script, solid}, objects are solid unless "solid" is false
+    pub fn load_objects(&mut self, path: &std::path::Path) {
+        let content = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(error) => panic!("Problem reading file: {:?}", error),
+        };
+        let defs: serde_json::Value = serde_json::from_str(&content).unwrap();
+
+        for def in defs.as_array().expect("Objects must be a list") {
+            self.add_object(MapObject {
+                pos: parse_pos(&def["pos"]).expect("Object needs a pos"),
+                interaction: parse_interaction(def).expect("Object needs a text or script"),
+                solid: def["solid"].as_bool().unwrap_or(true),
+            });
+        }
+    }
+
+    pub fn objects(&self) -> &[MapObject] {
+        &self.objects
+    }
+
+    /// The interaction for a movement cell, objects first and then the tiles in the cell from the top layer down
+    pub fn interaction_at(&self, pos: Vec2i) -> Option<&Interaction> {
+        if let Some(object) = self.objects.iter().find(|o| o.pos == pos) {
+            return Some(&object.interaction);
+        }
+        if !self.in_bounds(pos) {
+            return None;
+        }
+
+        let sf = self.sf as usize;
+        let (cx, cy) = (pos.x as usize * sf, pos.y as usize * sf);
+        for layer in self.layers.iter().rev() {
+            for row in cy..cy + sf {
+                for col in cx..cx + sf {
+                    let found = layer.at(row * self.dims.0 + col)
+                        .and_then(|id| self.tileset.interactions.get(&id.0));
+                    if found.is_some() {
+                        return found;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Load connections from a JSON list of { "dir", "dest", "offset" } objects
+    pub fn load_connections(&mut self, path: &std::path::Path) {
+        let content = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(error) => panic!("Problem reading file: {:?}", error),
+        };
+        let defs: serde_json::Value = serde_json::from_str(&content).unwrap();
+
+        for def in defs.as_array().expect("Connections must be a list") {
+            self.connections.push(Connection {
+                dir: parse_dir(&def["dir"]).expect("Connection needs a dir"),
+                dest: def["dest"].as_str().expect("Connection needs a dest").to_string(),
+                offset: def["offset"].as_i64().unwrap_or(0) as i32,
+            });
+        }
+    }
+
+    /// Size in movement cells
+    pub fn move_dims(&self) -> (i32, i32) {
+        ((self.dims.0 / self.sf as usize) as i32, (self.dims.1 / self.sf as usize) as i32)
+    }
+
+    /// Move the map so the given movement cell is under the player
+    pub fn center_on(&mut self, pos: Vec2i) {
+        self.position = Vec2i { x: PPOS.x - MOVE_SZ * pos.x, y: PPOS.y - MOVE_SZ * pos.y };
+    }
+
+    fn check_defined((w, _h): (usize, usize), map: &[usize], tileset: &Tileset) {
+        if let Some((pos, id)) = tileset.undefined_tiles(w, map).first() {
+            panic!("Tile {} at ({}, {}) has no definition in the tileset", id, pos.x, pos.y);
+        }
+    }
+
+    pub fn contains_solid((w, _h): (usize, usize), map: &[usize], Rect { pos, sz }: Rect, tileset: &Tileset) -> bool {
         for row in pos.y..pos.y+sz.y {
             for col in pos.x..pos.x+sz.x {
-                if !moveables.contains(&map[row as usize * w + col as usize]) {
+                if tileset.tiles[map[row as usize * w + col as usize]].solid {
                     return true;
                 }
             }
@@ -176,7 +1297,7 @@ impl Tilemap {
         false
     }
 
-    pub fn move_map((w, h): (usize, usize), map: &[usize], moveables: Vec<usize>, sf: i32) -> Vec<bool> {
+    pub fn move_map((w, h): (usize, usize), map: &[usize], tileset: &Tileset, sf: i32) -> Vec<bool> {
         let mut smap: Vec<bool> = Vec::with_capacity((w * h) / (sf*sf) as usize);
 
         for y in 0..h/sf as usize {
@@ -185,21 +1306,98 @@ impl Tilemap {
                     pos: Vec2i { x: sf * x as i32, y: sf * y as i32},
                     sz: Vec2i { x: sf, y: sf }
                 };
-                smap.push(!Self::contains_solid((w,h), map, r, moveables.clone()));
+                smap.push(!Self::contains_solid((w,h), map, r, tileset));
             }
         }
 
-        assert_eq!((w * h) / (sf*sf) as usize, smap.len(), "SolidMap is the wrong size!");
+        assert_eq!((w / sf as usize) * (h / sf as usize), smap.len(), "SolidMap is the wrong size!");
         smap
     }
 
-    pub fn can_move_to(&self, pos: Vec2i) -> bool {
-        self.movemap[pos.x as usize + (self.dims.0 / self.sf as usize) * pos.y as usize]
+    pub fn cell_map((w, h): (usize, usize), map: &[usize], tileset: &Tileset, sf: i32) -> Vec<Tile> {
+        let sf = sf as usize;
+        let mut cmap: Vec<Tile> = Vec::with_capacity((w * h) / (sf * sf));
+
+        for y in 0..h/sf {
+            for x in 0..w/sf {
+                let mut cell = Tile::default();
+                for row in sf*y..sf*(y+1) {
+                    for col in sf*x..sf*(x+1) {
+                        cell = cell.merge(tileset.tiles[map[row * w + col]]);
+                    }
+                }
+                cmap.push(cell);
+            }
+        }
+        cmap
+    }
+
+    /// Whether a position in movement cells is on the map
+    pub fn in_bounds(&self, pos: Vec2i) -> bool {
+        let sf = self.sf as usize;
+        pos.x >= 0 && pos.y >= 0 &&
+        (pos.x as usize) < self.dims.0 / sf &&
+        (pos.y as usize) < self.dims.1 / sf
     }
 
-    pub fn swap_can_move_to(pos: Vec2i) -> bool {
-        !matches!(pos.x, 0..=4 | 23..=27) &&
-        !matches!(pos.y, 0..=5 | 22..=26)
+    /// The combined tile properties of a movement cell
+    pub fn cell(&self, pos: Vec2i) -> Tile {
+        self.cellmap[pos.x as usize + (self.dims.0 / self.sf as usize) * pos.y as usize]
+    }
+
+    /// What happens when stepping from one movement cell to its neighbour in dir
+    pub fn step(&self, from: Vec2i, dir: usize) -> Step {
+        self.step_landing(from, dir, |land| self.in_bounds(land) && self.can_move_to(land))
+    }
+
+    /// Like step, with whether a ledge can be hopped onto a cell left to can_land,
+    /// so the landing cell can be on another map
+    fn step_landing(&self, from: Vec2i, dir: usize, can_land: impl Fn(Vec2i) -> bool) -> Step {
+        let mut to = from;
+        to.walk(dir);
+        if !self.in_bounds(to) {
+            return Step::Blocked;
+        }
+
+        if self.objects.iter().any(|o| o.solid && o.pos == to) {
+            return Step::Blocked;
+        }
+
+        let cell = self.cell(to);
+        if let Some(ledge) = cell.ledge {
+            let mut land = to;
+            land.walk(dir);
+            return if ledge == dir && can_land(land) {
+                Step::Jump(land)
+            } else {
+                Step::Blocked
+            };
+        }
+        if cell.counter {
+            let mut behind = to;
+            behind.walk(dir);
+            return Step::TalkOver(behind);
+        }
+        if cell.water {
+            return Step::Water;
+        }
+        if !self.can_move_to(to) {
+            return Step::Blocked;
+        }
+
+        if cell.ice {
+            Step::Slide
+        } else if cell.slow {
+            Step::Slow
+        } else if cell.grass {
+            Step::Grass
+        } else {
+            Step::Walk
+        }
+    }
+
+    pub fn can_move_to(&self, pos: Vec2i) -> bool {
+        self.movemap[pos.x as usize + (self.dims.0 / self.sf as usize) * pos.y as usize]
     }
 
     pub fn tile_id_at(&self, Vec2i { x, y }: Vec2i) -> (Vec2i, TileID) {
@@ -212,7 +1410,7 @@ impl Tilemap {
                 x: x * TILE_SZ + self.position.x,
                 y: y * TILE_SZ + self.position.y,
             },
-            self.map[y as usize * self.dims.0 + x as usize],
+            self.layers[0].map[y as usize * self.dims.0 + x as usize].unwrap(),
         )
     }
 
@@ -229,6 +1427,10 @@ impl Tilemap {
         (pos, self.tileset[tile_id])
     }
 
+    pub fn set_tick(&mut self, tick: usize) {
+        self.tick = tick;
+    }
+
     pub fn translate(&mut self, dir: usize) {
         match dir {
             DOWN => self.position.y -= 1,
@@ -239,31 +1441,403 @@ impl Tilemap {
         }
     }
 
+    /// Bake the map into chunks of size x size tiles, drawn as one image each
+    pub fn enable_cache(&mut self, size: usize) {
+        assert!(size > 0, "Chunks need at least one tile");
+        let cols = self.dims.0.div_ceil(size);
+        let rows = self.dims.1.div_ceil(size);
+        self.cache = Some(ChunkCache {
+            size,
+            cols,
+            below: vec![None; cols * rows],
+            above: vec![None; cols * rows],
+        });
+        self.invalidate_all();
+    }
+
+    pub fn disable_cache(&mut self) {
+        self.cache = None;
+    }
+
+    /// Re-bake the chunk holding a tile, call whenever that tile changes
+    pub fn invalidate(&mut self, Vec2i { x, y }: Vec2i) {
+        if let Some(size) = self.cache.as_ref().map(|c| c.size) {
+            self.bake_chunk(x as usize / size, y as usize / size);
+        }
+    }
+
+    /// Re-bake every chunk
+    pub fn invalidate_all(&mut self) {
+        if let Some(cache) = self.cache.as_ref() {
+            let (size, cols) = (cache.size, cache.cols);
+            for cy in 0..self.dims.1.div_ceil(size) {
+                for cx in 0..cols {
+                    self.bake_chunk(cx, cy);
+                }
+            }
+        }
+    }
+
+    fn bake_chunk(&mut self, cx: usize, cy: usize) {
+        let Some(cache) = self.cache.as_ref() else { return };
+        let size = cache.size;
+        let idx = cy * cache.cols + cx;
+        let xs = cx * size..(self.dims.0.min((cx + 1) * size));
+        let ys = cy * size..(self.dims.1.min((cy + 1) * size));
+
+        let animated = self.layers.iter().any(|layer| {
+            ys.clone().any(|y| xs.clone().any(|x| {
+                layer.map[y * self.dims.0 + x].is_some_and(|id| self.tileset.is_animated(id))
+            }))
+        });
+        let bake = |above: bool| {
+            if animated {
+                return None;
+            }
+            let sz = Vec2i { x: xs.len() as i32 * TILE_SZ, y: ys.len() as i32 * TILE_SZ };
+            let mut img = Image {
+                buffer: vec![(0, 0, 0, 0); (sz.x * sz.y) as usize].into_boxed_slice(),
+                sz,
+            };
+            let origin = Vec2i { x: -(xs.start as i32) * TILE_SZ, y: -(ys.start as i32) * TILE_SZ };
+            self.draw_region(&mut img, above, Masking::None, xs.clone(), ys.clone(), origin);
+            Some(img)
+        };
+        let below = bake(false);
+        let above = bake(true);
+
+        let cache = self.cache.as_mut().unwrap();
+        cache.below[idx] = below;
+        cache.above[idx] = above;
+    }
+
+    /// Columns and rows of tiles that overlap the screen
+    fn visible(&self, screen: &Image) -> (Range<usize>, Range<usize>) {
+        let x0 = (-self.position.x).div_euclid(TILE_SZ).clamp(0, self.dims.0 as i32);
+        let y0 = (-self.position.y).div_euclid(TILE_SZ).clamp(0, self.dims.1 as i32);
+        let x1 = (screen.sz.x - self.position.x + TILE_SZ - 1).div_euclid(TILE_SZ).clamp(0, self.dims.0 as i32);
+        let y1 = (screen.sz.y - self.position.y + TILE_SZ - 1).div_euclid(TILE_SZ).clamp(0, self.dims.1 as i32);
+        (x0 as usize..x1 as usize, y0 as usize..y1 as usize)
+    }
+
+    /// Draw the layers below sprites
     pub fn draw(&self, screen: &mut Image) {
-        for (y, row) in self.map.chunks_exact(self.dims.0).enumerate() {
-            // We are in tile coordinates at this point so we'll need to translate back to pixel units and world coordinates to draw.
-            let ypx = (y * TILE_SZ as usize) as i32 + self.position.y;
-            // Here we can iterate through the column index and tiles in the row in parallel
-            for (x, id) in row.iter().enumerate() {
-                let xpx = (x * TILE_SZ as usize) as i32 + self.position.x;
-                let frame = self.tileset.get_rect(*id);
-                screen.bitblt(&self.tileset.image, frame, Vec2i { x: xpx, y: ypx });
+        self.draw_layers(screen, false, Masking::None);
+    }
+
+    /// Draw the layers above sprites, call after entities have been drawn
+    pub fn draw_above(&self, screen: &mut Image) {
+        self.draw_layers(screen, true, Masking::None);
+    }
+
+    pub fn masked_draw(&self, screen: &mut Image) {
+        self.draw_layers(screen, false, Masking::Covered(&self.mask));
+    }
+
+    pub fn masked_draw_above(&self, screen: &mut Image) {
+        self.draw_layers(screen, true, Masking::Covered(&self.mask));
+    }
+
+    /// Draw the layers above sprites only where another map's mask has been revealed
+    pub fn revealed_draw_above(&self, screen: &mut Image, mask: &MapMask) {
+        self.draw_layers(screen, true, Masking::Revealed(mask));
+    }
+
+    fn draw_layers(&self, screen: &mut Image, above: bool, masking: Masking) {
+        let (xs, ys) = self.visible(screen);
+
+        // The mask changes every step so masked draws skip the cache
+        let cache = match (&self.cache, masking) {
+            (Some(cache), Masking::None) => cache,
+            _ => return self.draw_region(screen, above, masking, xs, ys, self.position),
+        };
+
+        let size = cache.size;
+        for cy in ys.start / size..ys.end.div_ceil(size) {
+            for cx in xs.start / size..xs.end.div_ceil(size) {
+                let chunk = if above { &cache.above } else { &cache.below };
+                match &chunk[cy * cache.cols + cx] {
+                    Some(img) => screen.bitblt(
+                        img,
+                        Rect { pos: Vec2i { x: 0, y: 0 }, sz: img.sz },
+                        Vec2i {
+                            x: (cx * size) as i32 * TILE_SZ + self.position.x,
+                            y: (cy * size) as i32 * TILE_SZ + self.position.y,
+                        }
+                    ),
+                    None => self.draw_region(
+                        screen,
+                        above,
+                        Masking::None,
+                        cx * size..self.dims.0.min((cx + 1) * size),
+                        cy * size..self.dims.1.min((cy + 1) * size),
+                        self.position
+                    ),
+                }
             }
         }
     }
 
-    pub fn masked_draw(&mut self, screen: &mut Image) {
-        for (y, row) in self.map.chunks_exact(self.dims.0).enumerate() {
-            // We are in tile coordinates at this point so we'll need to translate back to pixel units and world coordinates to draw.
-            let ypx = (y * TILE_SZ as usize) as i32 + self.position.y;
-            // Here we can iterate through the column index and tiles in the row in parallel
-            for (x, id) in row.iter().enumerate() {
-                let xpx = (x * TILE_SZ as usize) as i32 + self.position.x;
-                let frame = self.tileset.get_rect(*id);
-                if self.mask.at(x, y) {
-                    screen.bitblt(&self.tileset.image, frame, Vec2i { x: xpx, y: ypx });
+    /// Draw the tiles in columns xs and rows ys, with the map's top left corner at origin
+    fn draw_region(&self, screen: &mut Image, above: bool, masking: Masking, xs: Range<usize>, ys: Range<usize>, origin: Vec2i) {
+        for layer in self.layers.iter().filter(|l| l.above == above) {
+            for y in ys.clone() {
+                // We are in tile coordinates at this point so we'll need to translate back to pixel units and world coordinates to draw.
+                let ypx = (y * TILE_SZ as usize) as i32 + origin.y;
+                for x in xs.clone() {
+                    let xpx = (x * TILE_SZ as usize) as i32 + origin.x;
+                    if let Some(id) = layer.map[y * self.dims.0 + x] {
+                        let frame = self.tileset.get_rect(self.tileset.frame(id, self.tick));
+                        match masking.coverage(x, y) {
+                            0 => (),
+                            255 => screen.bitblt(&self.tileset.image, frame, Vec2i { x: xpx, y: ypx }),
+                            c => screen.bitblt_faded(&self.tileset.image, frame, Vec2i { x: xpx, y: ypx }, c as f32 / 255.0),
+                        }
+                    }
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animated_tile_picks_the_frame_for_a_tick() {
+        let anim = AnimatedTile::new(vec![5, 6, 7], vec![2, 3, 1]);
+        let frames = (0..14).map(|tick| anim.frame_at(tick).0).collect::<Vec<usize>>();
+        assert_eq!(frames, [5, 5, 6, 6, 6, 7, 5, 5, 6, 6, 6, 7, 5, 5]);
+
+        // Frames that take no time are never shown, and all of them taking none holds the first
+        assert_eq!(AnimatedTile::new(vec![1, 2, 3], vec![0, 4, 0]).frame_at(9), TileID(2));
+        assert_eq!(AnimatedTile::new(vec![1, 2], vec![0, 0]).frame_at(9), TileID(1));
+    }
+
+    #[test]
+    fn tileset_only_animates_the_base_tile() {
+        let mut tileset = Tileset::new(vec![Tile::new(false); 4], Rc::new(Image::new(Vec2i { x: 32, y: 8 })));
+        tileset.add_animation(1, AnimatedTile::new(vec![1, 2, 3], vec![10, 10, 10]));
+        assert!(tileset.is_animated(TileID(1)));
+        assert!(!tileset.is_animated(TileID(2)));
+        assert_eq!(tileset.frame(TileID(1), 25), TileID(3));
+        assert_eq!(tileset.frame(TileID(1), 30), TileID(1));
+        assert_eq!(tileset.frame(TileID(0), 25), TileID(0));
+    }
+    /// Neighbours at the given (dx, dy) offsets match, the rest don't
+    fn around(cells: &[(i32, i32)]) -> impl Fn(i32, i32) -> bool + '_ {
+        move |dx, dy| cells.contains(&(dx, dy))
+    }
+
+    #[test]
+    fn edges16_indexes_by_matching_edges() {
+        let group = Autotile::new("path", AutotileRule::Edges16, (100..116).collect());
+        assert_eq!(group.variant(around(&[])), 100);
+        assert_eq!(group.variant(around(&[(0, -1)])), 101);
+        assert_eq!(group.variant(around(&[(1, 0), (-1, 0)])), 110);
+        assert_eq!(group.variant(around(&[(0, -1), (1, 0), (0, 1), (-1, 0)])), 115);
+        // Corners don't matter to edges
+        assert_eq!(group.variant(around(&[(1, -1), (-1, 1)])), 100);
+    }
+
+    #[test]
+    fn blob47_only_counts_corners_between_matching_edges() {
+        let group = Autotile::new("water", AutotileRule::Blob47, (200..247).collect());
+        let all = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+        assert_eq!((0..256).filter(|m| Autotile::blob_valid(*m)).count(), 47);
+
+        // An isolated tile is the first variant and a surrounded one the last
+        assert_eq!(group.variant(around(&[])), 200);
+        assert_eq!(group.variant(around(&all)), 246);
+
+        // Corners on their own, or with only one of their edges, are ignored
+        assert_eq!(group.variant(around(&[(1, -1), (1, 1), (-1, 1), (-1, -1)])), 200);
+        assert_eq!(group.variant(around(&[(0, -1), (1, -1)])), group.variant(around(&[(0, -1)])));
+        assert_eq!(group.variant(around(&[(1, 0), (1, -1)])), group.variant(around(&[(1, 0)])));
+
+        // N and E are masks 1 and 4, below them only 0 and 1 are valid; the NE corner makes mask 7
+        assert_eq!(group.variant(around(&[(0, -1)])), 201);
+        assert_eq!(group.variant(around(&[(0, -1), (1, 0)])), 203);
+        assert_eq!(group.variant(around(&[(0, -1), (1, -1), (1, 0)])), 204);
+
+        // Every neighbour set lands on one of the 47 variants
+        for m in 0..256usize {
+            let cells = all.iter().enumerate().filter(|(i, _)| m & (1 << i) != 0).map(|(_, c)| *c).collect::<Vec<(i32, i32)>>();
+            assert!(group.contains(group.variant(around(&cells))));
+        }
+    }
+
+    #[test]
+    fn autotile_at_treats_the_map_edge_as_matching() {
+        let tileset = Rc::new(Tileset::new(vec![Tile::new(false); 20], Rc::new(Image::new(Vec2i { x: 160, y: 8 }))));
+        let group = Autotile::new("path", AutotileRule::Edges16, (4..20).collect());
+        let mut ids = vec![
+            0, 0, 0,
+            0, 4, 0,
+            0, 0, 0,
+        ];
+        let map = Tilemap::new(Vec2i { x: 0, y: 0 }, (3, 3), tileset.clone(), ids.clone(), 1);
+        assert_eq!(map.autotile_at(Vec2i { x: 1, y: 1 }, &group), 4);
+        assert_eq!(map.autotile_at(Vec2i { x: 0, y: 0 }, &group), 4 + (1 | 8));
+
+        // Painting next to a tile of the group fixes up both of them
+        Tilemap::paint_autotile(&mut ids, (3, 3), Vec2i { x: 2, y: 1 }, &group);
+        assert_eq!(ids[4], 4 + 2);
+        assert_eq!(ids[5], 4 + (2 | 8));
+    }
+
+    #[test]
+    fn soft_brush_fades_out_toward_its_edge() {
+        let mut mask = MapMask::new((9, 9));
+        mask.paint((4.5, 4.5), &Brush::Square(3.0), 2.0);
+
+        // Fully revealed within a tile of the center, half way at 2 and untouched from 3 out
+        let row = (0..9).map(|x| mask.coverage(x, 4)).collect::<Vec<u8>>();
+        assert_eq!(row, [255, 255, 128, 0, 0, 0, 128, 255, 255]);
+        assert_eq!(mask.coverage(6, 6), 128);
+        assert_eq!(mask.swapc, 9);
+
+        // A weaker stroke over the same tiles never covers them back up
+        mask.paint((4.5, 4.5), &Brush::Square(3.0), 3.0);
+        assert_eq!(mask.coverage(5, 4), 0);
+        assert_eq!(mask.coverage(6, 4), 128);
+        assert_eq!(mask.swapc, 9);
+    }
+
+    #[test]
+    fn brush_off_the_map_edge_is_clipped() {
+        let mut mask = MapMask::new((4, 4));
+        mask.paint((0.5, 0.5), &Brush::Circle(1.5), 1.0);
+
+        assert_eq!(mask.coverage(0, 0), 0);
+        assert_eq!(mask.coverage(1, 0), 128);
+        assert_eq!(mask.coverage(0, 1), 128);
+        assert_eq!(mask.coverage(2, 0), 255);
+        assert_eq!(mask.swapc, 1);
+    }
+
+    #[test]
+    fn set_tile_is_recorded_replayed_and_redrawn() {
+        let mut sheet = Image::new(Vec2i { x: 16, y: 8 });
+        sheet.draw_rect(&Rect { pos: Vec2i { x: 8, y: 0 }, sz: Vec2i { x: 8, y: 8 } }, (255, 255, 255, 255));
+        let tileset = Rc::new(Tileset::new(vec![Tile::new(false), Tile::new(true)], Rc::new(sheet)));
+        let fresh = || {
+            let mut map = Tilemap::new(Vec2i { x: 0, y: 0 }, (4, 4), tileset.clone(), vec![0; 16], 2);
+            map.enable_cache(2);
+            map
+        };
+        let pixel = |map: &Tilemap| {
+            let mut screen = Image::new(Vec2i { x: 32, y: 32 });
+            map.draw(&mut screen);
+            screen.buffer[(2 * TILE_SZ + 1) as usize * 32 + (3 * TILE_SZ + 1) as usize]
+        };
+
+        // The chunk baked before the change has to be baked again to show it
+        let mut map = fresh();
+        assert_eq!(pixel(&map), (0, 0, 0, 255));
+        map.set_tile(Vec2i { x: 3, y: 2 }, 1);
+        map.set_tile(Vec2i { x: 3, y: 2 }, 1);
+        assert_eq!(map.changes(), [TileChange { pos: Vec2i { x: 3, y: 2 }, id: 1 }]);
+        assert!(!map.can_move_to(Vec2i { x: 1, y: 1 }));
+        assert_eq!(pixel(&map), (255, 255, 255, 255));
+
+        let mut replayed = fresh();
+        replayed.apply_changes(map.changes());
+        assert_eq!(replayed.changes(), map.changes());
+        assert!(!replayed.can_move_to(Vec2i { x: 1, y: 1 }));
+        assert_eq!(pixel(&replayed), (255, 255, 255, 255));
+    }
+
+    /// 4x4 maps at one tile per movement cell: 0 walks, 1 is solid and 2 is a ledge hopped down
+    fn seam_map(ids: Vec<usize>, connections: Vec<Connection>) -> Tilemap {
+        let tiles = vec![Tile::new(false), Tile::new(true), Tile { ledge: Some(DOWN), ..Default::default() }];
+        let tileset = Rc::new(Tileset::new(tiles, Rc::new(Image::new(Vec2i { x: 24, y: 8 }))));
+        let mut map = Tilemap::new(Vec2i { x: 0, y: 0 }, (4, 4), tileset, ids, 1);
+        map.connections = connections;
+        map
+    }
+
+    fn town() -> Tilemap {
+        seam_map(
+            vec![
+                0, 0, 0, 0,
+                0, 0, 0, 0,
+                0, 0, 0, 0,
+                0, 2, 2, 0,
+            ],
+            vec![
+                Connection { dir: DOWN, dest: "route".to_string(), offset: 0 },
+                Connection { dir: RIGHT, dest: "beach".to_string(), offset: 1 },
+            ]
+        )
+    }
+
+    fn route() -> Tilemap {
+        seam_map(
+            vec![
+                0, 0, 1, 0,
+                0, 0, 0, 0,
+                0, 0, 0, 0,
+                0, 0, 0, 0,
+            ],
+            vec![Connection { dir: UP, dest: "town".to_string(), offset: 0 }]
+        )
+    }
+
+    fn beach() -> Tilemap {
+        seam_map(vec![0; 16], vec![Connection { dir: LEFT, dest: "town".to_string(), offset: -1 }])
+    }
+
+    fn world() -> ConnectedMaps {
+        let mut loader = MapLoader::new();
+        loader.add("town", town);
+        loader.add("route", route);
+        loader.add("beach", beach);
+        ConnectedMaps::new(loader, "town", 1)
+    }
+
+    #[test]
+    fn walking_across_a_seam_steps_onto_the_neighbour() {
+        let mut maps = world();
+        maps.stream(Vec2i { x: 0, y: 0 });
+        assert_eq!(maps.loaded().count(), 0);
+        assert_eq!(maps.step(Vec2i { x: 0, y: 3 }, DOWN), Step::Blocked);
+
+        maps.stream(Vec2i { x: 0, y: 3 });
+        assert_eq!(maps.loaded().collect::<Vec<&str>>(), ["route"]);
+        assert_eq!(maps.step(Vec2i { x: 0, y: 3 }, DOWN), Step::Walk);
+        assert_eq!(maps.step(Vec2i { x: 3, y: 3 }, DOWN), Step::Walk);
+        assert_eq!(maps.step(Vec2i { x: 0, y: 3 }, LEFT), Step::Blocked);
+    }
+
+    #[test]
+    fn ledge_on_the_last_row_lands_on_the_neighbour() {
+        let mut maps = world();
+        // With nothing loaded below, there's nowhere to land
+        assert_eq!(maps.step(Vec2i { x: 1, y: 2 }, DOWN), Step::Blocked);
+
+        maps.stream(Vec2i { x: 1, y: 2 });
+        assert_eq!(maps.step(Vec2i { x: 1, y: 2 }, DOWN), Step::Jump(Vec2i { x: 1, y: 4 }));
+        // The cell across the seam from this ledge is solid
+        assert_eq!(maps.step(Vec2i { x: 2, y: 2 }, DOWN), Step::Blocked);
+        assert_eq!(maps.active.step(Vec2i { x: 1, y: 2 }, DOWN), Step::Blocked);
+    }
+
+    #[test]
+    fn cross_renumbers_positions_into_the_new_map() {
+        let mut maps = world();
+        maps.stream(Vec2i { x: 3, y: 3 });
+        assert_eq!(maps.cross(Vec2i { x: 3, y: 3 }), Vec2i { x: 3, y: 3 });
+        assert_eq!(maps.name, "town");
+
+        // The beach is shifted one cell down the town's east edge
+        assert_eq!(maps.cross(Vec2i { x: 4, y: 3 }), Vec2i { x: 0, y: 2 });
+        assert_eq!(maps.name, "beach");
+        assert_eq!(maps.loaded().collect::<Vec<&str>>(), ["town"]);
+        assert_eq!(maps.step(Vec2i { x: 0, y: 2 }, LEFT), Step::Walk);
+
+        assert_eq!(maps.cross(Vec2i { x: -1, y: 0 }), Vec2i { x: 3, y: 1 });
+        assert_eq!(maps.name, "town");
+    }
+}
@@ -0,0 +1,177 @@
+use crate::tiles::parse_pos;
+use crate::types::{Rect, Vec2i};
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+/// The area a zone covers, in movement cells
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ZoneShape {
+    Rect(Rect),
+    Cells(HashSet<Vec2i>),
+}
+
+/// A named region that reports who walks in and out of it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Zone {
+    pub name: String,
+    pub shape: ZoneShape,
+}
+
+impl Zone {
+    pub fn contains(&self, pos: Vec2i) -> bool {
+        match &self.shape {
+            ZoneShape::Rect(r) => {
+                pos.x >= r.pos.x && pos.y >= r.pos.y &&
+                pos.x < r.pos.x + r.sz.x && pos.y < r.pos.y + r.sz.y
+            },
+            ZoneShape::Cells(cells) => cells.contains(&pos),
+        }
+    }
+}
+
+/// Who moved, NPCs are told apart by their id
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Actor {
+    Player,
+    Npc(i32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerKind {
+    /// The actor was outside the zone last update
+    Enter,
+    /// The actor is still inside
+    Stay,
+    /// The actor was inside the zone last update
+    Exit,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TriggerEvent {
+    pub kind: TriggerKind,
+    pub zone: String,
+    pub actor: Actor,
+}
+
+/// A map's zones and which of them each actor was in at its last update
+#[derive(Default)]
+pub struct Triggers {
+    pub zones: Vec<Zone>,
+    inside: HashMap<Actor, HashSet<usize>>,
+}
+
+impl Triggers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a list of {name, rect: [x, y, w, h]} or {name, cells: [[x, y], ...]} in movement cells
+    pub fn from_json(path: &std::path::Path) -> Self {
+        let content = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(error) => panic!("Problem reading file: {:?}", error),
+        };
+        let defs: serde_json::Value = serde_json::from_str(&content).unwrap();
+
+        let mut triggers = Self::new();
+        for def in defs.as_array().expect("Zones must be a list") {
+            let name = def["name"].as_str().expect("Zone needs a name").to_string();
+            let shape = if let Some(cells) = def["cells"].as_array() {
+                ZoneShape::Cells(
+                    cells.iter()
+                        .map(|c| parse_pos(c).unwrap_or_else(|| panic!("Zone {} has an invalid cell", name)))
+                        .collect()
+                )
+            } else {
+                let r = def["rect"]
+                    .as_array()
+                    .filter(|r| r.len() == 4)
+                    .unwrap_or_else(|| panic!("Zone {} needs a rect or cells", name))
+                    .iter()
+                    .map(|v| v.as_i64().unwrap_or_else(|| panic!("Zone {} has an invalid rect", name)) as i32)
+                    .collect::<Vec<i32>>();
+                ZoneShape::Rect(Rect { pos: Vec2i { x: r[0], y: r[1] }, sz: Vec2i { x: r[2], y: r[3] } })
+            };
+            triggers.add(Zone { name, shape });
+        }
+        triggers
+    }
+
+    pub fn add(&mut self, zone: Zone) {
+        self.zones.push(zone);
+    }
+
+    /// Tell the zones where an actor is now. Stay fires on every update the actor is still inside.
+    pub fn update(&mut self, actor: Actor, pos: Vec2i) -> Vec<TriggerEvent> {
+        let now = self.zones.iter()
+            .enumerate()
+            .filter(|(_, z)| z.contains(pos))
+            .map(|(i, _)| i)
+            .collect::<HashSet<usize>>();
+        let before = self.inside.insert(actor, now.clone()).unwrap_or_default();
+
+        // Exits first, so moving between zones reads in order
+        let mut events = Vec::new();
+        for (i, zone) in self.zones.iter().enumerate() {
+            if before.contains(&i) && !now.contains(&i) {
+                events.push(TriggerEvent { kind: TriggerKind::Exit, zone: zone.name.clone(), actor });
+            }
+        }
+        for (i, zone) in self.zones.iter().enumerate() {
+            let kind = match (before.contains(&i), now.contains(&i)) {
+                (false, true) => TriggerKind::Enter,
+                (true, true) => TriggerKind::Stay,
+                _ => continue,
+            };
+            events.push(TriggerEvent { kind, zone: zone.name.clone(), actor });
+        }
+        events
+    }
+
+    /// Forget an actor, e.g. an NPC that left the map, without firing exits
+    pub fn remove(&mut self, actor: Actor) {
+        self.inside.remove(&actor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(triggers: &mut Triggers, actor: Actor, x: i32, y: i32) -> Vec<(TriggerKind, String)> {
+        triggers.update(actor, Vec2i { x, y })
+            .into_iter()
+            .map(|e| {
+                assert_eq!(e.actor, actor);
+                (e.kind, e.zone)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn zones_report_enter_stay_and_exit_in_order() {
+        let mut triggers = Triggers::new();
+        triggers.add(Zone {
+            name: "hall".to_string(),
+            shape: ZoneShape::Rect(Rect { pos: Vec2i { x: 0, y: 0 }, sz: Vec2i { x: 2, y: 1 } }),
+        });
+        triggers.add(Zone { name: "door".to_string(), shape: ZoneShape::Cells([Vec2i { x: 2, y: 0 }].into()) });
+        let (enter, stay, exit) = (TriggerKind::Enter, TriggerKind::Stay, TriggerKind::Exit);
+        let player = Actor::Player;
+
+        assert_eq!(events(&mut triggers, player, 0, 0), [(enter, "hall".to_string())]);
+        assert_eq!(events(&mut triggers, player, 1, 0), [(stay, "hall".to_string())]);
+        // Leaving one zone for the next reads as an exit, then an enter
+        assert_eq!(events(&mut triggers, player, 2, 0), [(exit, "hall".to_string()), (enter, "door".to_string())]);
+        assert_eq!(events(&mut triggers, player, 5, 5), [(exit, "door".to_string())]);
+        assert_eq!(events(&mut triggers, player, 5, 6), []);
+
+        // Each actor is tracked on its own, and one that's forgotten enters again without exiting
+        let npc = Actor::Npc(3);
+        assert_eq!(events(&mut triggers, npc, 1, 0), [(enter, "hall".to_string())]);
+        assert_eq!(events(&mut triggers, player, 0, 0), [(enter, "hall".to_string())]);
+        triggers.remove(npc);
+        assert_eq!(events(&mut triggers, npc, 1, 0), [(enter, "hall".to_string())]);
+    }
+}
@@ -13,6 +13,15 @@ pub const UP: usize = 1;
 pub const LEFT: usize = 2;
 pub const RIGHT: usize = 3;
 pub const SPACE: usize = 4;
+pub const ESCAPE: usize = 5;
+pub const BACK: usize = 6;
+pub const ENTER: usize = 7;
+pub const SHIFT: usize = 8;
+pub const TAB: usize = 9;
+/// Letter keys follow in alphabetical order, KEY_A + 25 is Z
+pub const KEY_A: usize = 10;
+
+pub const NUM_KEYS: usize = KEY_A + 26;
 
 pub const PPOS: Vec2i = Vec2i { 
     x: (WIDTH as i32 / 2) - 8, 
@@ -128,13 +137,17 @@ impl Image {
         &self.buffer
     }
     pub fn from_file(p: &std::path::Path) -> Self {
-        let img = image_reading::open(p).unwrap().into_rgba8();
+        Self::try_from_file(p).unwrap_or_else(|error| panic!("Problem reading {:?}: {}", p, error))
+    }
+    /// Like from_file, returning why the image couldn't be read instead of panicking
+    pub fn try_from_file(p: &std::path::Path) -> Result<Self, String> {
+        let img = image_reading::open(p).map_err(|error| error.to_string())?.into_rgba8();
         let sz = Vec2i {
             x: img.width() as i32,
             y: img.height() as i32,
         };
         let img = img.into_vec();
-        Self {
+        Ok(Self {
             buffer: img
                 .chunks_exact(4)
                 .map(|px| {
@@ -146,7 +159,7 @@ impl Image {
                 })
                 .collect(),
             sz,
-        }
+        })
     }
 
     pub fn size(&self) -> (i32, i32) {
@@ -157,6 +170,16 @@ impl Image {
         self.buffer.fill(c);
     }
 
+    /// Blend every pixel towards black, 0.0 leaves the image as is and 1.0 is fully black
+    pub fn darken(&mut self, amount: f32) {
+        let keep = 1.0 - amount.clamp(0.0, 1.0);
+        for px in self.buffer.iter_mut() {
+            px.0 = (px.0 as f32 * keep) as u8;
+            px.1 = (px.1 as f32 * keep) as u8;
+            px.2 = (px.2 as f32 * keep) as u8;
+        }
+    }
+
     pub fn draw_rect(&mut self, rect: &Rect, color: Color) {
         for y in (rect.pos.y)..(rect.pos.y + rect.sz.y) {
             for x in (rect.pos.x)..(rect.pos.x + rect.sz.x) {
@@ -203,6 +226,18 @@ impl Image {
     }
 
     pub fn bitblt(&mut self, src: &Image, from: Rect, to: Vec2i) {
+        self.bitblt_faded(src, from, to, 1.0);
+    }
+
+    /// Like bitblt, but the source is drawn at an opacity between 0.0 and 1.0
+    pub fn bitblt_faded(&mut self, src: &Image, from: Rect, to: Vec2i, opacity: f32) {
+        self.blend(src, from, to, opacity, None);
+    }
+    /// Blit with every src pixel exactly matching ink drawn as color instead
+    pub fn bitblt_recolored(&mut self, src: &Image, from: Rect, to: Vec2i, ink: Color, color: Color) {
+        self.blend(src, from, to, 1.0, Some((ink, color)));
+    }
+    fn blend(&mut self, src: &Image, from: Rect, to: Vec2i, opacity: f32, recolor: Option<(Color, Color)>) {
         assert!(Rect {
             pos: Vec2i { x: 0, y: 0 },
             sz: src.sz
@@ -247,16 +282,17 @@ impl Image {
             let from_cols = row_a[from_row_start..from_row_stop].iter();
             let from_cols = Box::new(from_cols) as Box<dyn Iterator<Item = &Color>>;
             for (to, from) in to_cols.zip(from_cols) {
+                let from = match recolor {
+                    Some((ink, color)) if *from == ink => color,
+                    _ => *from,
+                };
                 let ta = to.3 as f32 / 255.0;
-                let fa = from.3 as f32 / 255.0;
-                to.0 = from
-                    .0
+                let fa = from.3 as f32 / 255.0 * opacity;
+                to.0 = ((from.0 as f32 * opacity).round() as u8)
                     .saturating_add((to.0 as f32 * (1.0 - fa)).round() as u8);
-                to.1 = from
-                    .1
+                to.1 = ((from.1 as f32 * opacity).round() as u8)
                     .saturating_add((to.1 as f32 * (1.0 - fa)).round() as u8);
-                to.2 = from
-                    .2
+                to.2 = ((from.2 as f32 * opacity).round() as u8)
                     .saturating_add((to.2 as f32 * (1.0 - fa)).round() as u8);
                 to.3 = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
             }
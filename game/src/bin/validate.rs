@@ -0,0 +1,244 @@
+use game::world;
+
+use std::collections::HashSet;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::rc::Rc;
+
+use engine::locale::Locale;
+use engine::text::{Font, Markup};
+use engine::tiles::{Tilemap, Tileset};
+use engine::types::{Image, Vec2i, TILE_SZ};
+
+/// Pixels a word and the space after it can take in a textbox row, the last cell is kept for the arrow
+const ROW_PX: i32 = 19 * TILE_SZ;
+
+// Usage: validate [content dir]
+fn main() {
+    let content = std::env::args().nth(1).map(PathBuf::from).unwrap_or_else(world::content_dir);
+
+    let mut problems = Vec::new();
+    if !content.join(world::FONT).exists() {
+        println!("{}: missing", world::FONT);
+        exit(1);
+    }
+    let font = Font::try_from_json(content.join(world::FONT).as_path())
+        .map_err(|error| problems.push(format!("{}: {}", world::FONT, error)))
+        .ok();
+    let root = content.join(world::LOCALES);
+    if !root.join(world::DEFAULT_LOCALE).is_dir() {
+        println!("{}/{}: missing", world::LOCALES, world::DEFAULT_LOCALE);
+        exit(1);
+    }
+    let default = Locale::from_dir(root.join(world::DEFAULT_LOCALE).as_path(), world::DEFAULT_LOCALE);
+    for (i, level) in world::LEVELS.iter().enumerate() {
+        check_level(i + 1, level, &content, &default, &mut problems);
+    }
+    let ids = ["OPEN", "END", "YES", "NO", "NAME_DEL", "NAME_END"].into_iter()
+        .chain(world::TEXT_SPEEDS.map(|(id, _)| id))
+        .chain(world::NAMES.map(|(_, id)| id));
+    for id in ids {
+        if default.get(world::TEXT, id).is_none() {
+            problems.push(format!("{}/{}: no \"{}\" in {}", world::LOCALES, world::DEFAULT_LOCALE, id, world::TEXT));
+        }
+    }
+    for c in world::NAME_CHARS.chars() {
+        if font.as_ref().is_some_and(|font| font.lookup(&c.to_string()).is_none()) {
+            problems.push(format!("{}: no glyph for name character '{}'", world::FONT, c));
+        }
+    }
+
+    // LOCALES -> every string each one has, and what it leaves to the default
+    for name in Locale::available(root.as_path()) {
+        let locale = Locale::load(root.as_path(), &name, world::DEFAULT_LOCALE);
+        for (table, id) in locale.missing() {
+            problems.push(format!("{}/{}: no \"{}\" in {}, falls back to {}", world::LOCALES, name, id, table, world::DEFAULT_LOCALE));
+        }
+        // Text can only be measured with a font that loaded
+        if let Some(font) = font.as_ref() {
+            for (table, id) in locale.ids() {
+                let at = format!("{}/{}/{}.json \"{}\"", world::LOCALES, name, table, id);
+                check_text(&at, locale.get(&table, &id).unwrap(), font, &mut problems);
+            }
+        }
+    }
+
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("{} levels OK", world::LEVELS.len());
+    } else {
+        println!("{} problems", problems.len());
+        exit(1);
+    }
+}
+
+fn check_level(n: usize, level: &world::Level, content: &Path, locale: &Locale, problems: &mut Vec<String>) {
+    let map = check_map(level, content, problems);
+
+    let dlg = locale.table(level.dlg);
+    if dlg.is_empty() {
+        problems.push(format!("{}/{}/{}.json: missing", world::LOCALES, locale.name, level.dlg));
+    }
+    if locale.get(world::TEXT, level.fin).is_none() {
+        problems.push(format!("level {}: no \"{}\" in {}", n, level.fin, world::TEXT));
+    }
+    if !content.join(level.npc_sheet).exists() {
+        problems.push(format!("{}: missing", level.npc_sheet));
+    }
+
+    // NPCS
+    let mut taken = HashSet::new();
+    let npcs = world::npc_defs(level, content).unwrap_or_else(|problem| {
+        problems.push(problem);
+        vec![]
+    });
+    for (_, _, pos, key) in npcs {
+        let at = format!("level {} NPC {} at ({}, {})", n, key, pos.x, pos.y);
+        if !dlg.contains_key(&key) {
+            problems.push(format!("{}: no \"{}\" in {}", at, key, level.dlg));
+        }
+        if locale.get(world::SPEAKERS, &key).is_none() {
+            problems.push(format!("{}: no \"{}\" in {}", at, key, world::SPEAKERS));
+        }
+        if !taken.insert(pos) {
+            problems.push(format!("{}: cell is shared with another NPC", at));
+        }
+        if let Some(map) = map.as_ref() {
+            if !map.in_bounds(pos) {
+                problems.push(format!("{}: outside the map", at));
+            } else if !map.can_move_to(pos) {
+                problems.push(format!("{}: on a solid cell", at));
+            }
+        }
+    }
+
+    // SIGNS
+    for &(pos, key) in level.signs {
+        let at = format!("level {} sign {} at ({}, {})", n, key, pos.x, pos.y);
+        if !dlg.contains_key(key) {
+            problems.push(format!("{}: no \"{}\" in {}", at, key, level.dlg));
+        }
+        if !taken.insert(pos) {
+            problems.push(format!("{}: cell is shared with another NPC or sign", at));
+        }
+        if map.as_ref().is_some_and(|map| !map.in_bounds(pos)) {
+            problems.push(format!("{}: outside the map", at));
+        }
+    }
+}
+
+/// Loads the level's map through the engine if its files are sound
+fn check_map(level: &world::Level, content: &Path, problems: &mut Vec<String>) -> Option<Tilemap> {
+    for file in [level.tilesheet, level.tiles, level.map] {
+        if !content.join(file).exists() {
+            problems.push(format!("{}: missing", file));
+            return None;
+        }
+    }
+
+    let raw = match read_to_string(content.join(level.map)) {
+        Ok(raw) => raw,
+        Err(error) => {
+            problems.push(format!("{}: {}", level.map, error));
+            return None;
+        }
+    };
+    let (w, h) = world::MAP_DIMS;
+    let rows = raw.lines().filter(|l| !l.trim().is_empty()).collect::<Vec<&str>>();
+    let mut ok = true;
+    if rows.len() != h {
+        problems.push(format!("{}: {} rows, expected {}", level.map, rows.len(), h));
+        ok = false;
+    }
+
+    let mut ids = Vec::with_capacity(w * h);
+    for (y, row) in rows.iter().enumerate() {
+        let cells = row.split(',').collect::<Vec<&str>>();
+        if cells.len() != w {
+            problems.push(format!("{}:{}: {} cells, expected {}", level.map, y + 1, cells.len(), w));
+            ok = false;
+        }
+        for (x, cell) in cells.iter().enumerate() {
+            match cell.trim().parse::<usize>() {
+                Ok(id) => ids.push(id),
+                Err(_) => {
+                    problems.push(format!("{}:{}:{}: \"{}\" is not a tile ID", level.map, y + 1, x + 1, cell.trim()));
+                    ok = false;
+                }
+            }
+        }
+    }
+    if !ok {
+        return None;
+    }
+
+    let tilesheet = match Image::try_from_file(content.join(level.tilesheet).as_path()) {
+        Ok(image) => Rc::new(image),
+        Err(error) => {
+            problems.push(format!("{}: {}", level.tilesheet, error));
+            return None;
+        }
+    };
+    let tileset = match Tileset::try_from_json(tilesheet, content.join(level.tiles).as_path()) {
+        Ok(tileset) => Rc::new(tileset),
+        Err(error) => {
+            problems.push(format!("{}: {}", level.tiles, error));
+            return None;
+        }
+    };
+    let undefined = tileset.undefined_tiles(w, &ids);
+    for (pos, id) in undefined.iter() {
+        problems.push(format!("{}:{}:{}: tile {} is not in {}", level.map, pos.y + 1, pos.x + 1, id, level.tiles));
+    }
+    if !undefined.is_empty() {
+        return None;
+    }
+
+    Some(Tilemap::new(Vec2i { x: 0, y: 0 }, world::MAP_DIMS, tileset, ids, 2))
+}
+
+/// Markup that can't be read, words the textbox has to hyphenate, and characters with no glyph
+fn check_text(at: &str, text: &str, font: &Font, problems: &mut Vec<String>) {
+    let mut missing = HashSet::new();
+
+    // Tags take no room, except variables which are checked where they're set
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        plain.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('}') else {
+            problems.push(format!("{}: unclosed markup \"{}\"", at, &rest[open..]));
+            rest = "";
+            break;
+        };
+        let tag = &rest[open + 1..open + close];
+        match Markup::parse(tag) {
+            Some(Markup::Page) => plain.push(' '),
+            Some(_) => (),
+            None => problems.push(format!("{}: invalid markup {{{}}}", at, tag)),
+        }
+        rest = &rest[open + close + 1..];
+    }
+    plain.push_str(rest);
+
+    let mut open = Vec::new();
+    for word in plain.split_whitespace() {
+        let mut px = font.glyphs[font.space].advance;
+        let chars = word.chars().collect::<Vec<char>>();
+        let mut i = 0;
+        while i < chars.len() {
+            let (id, len) = font.next_glyph(&chars[i..], &mut open);
+            if id.is_none() && missing.insert(chars[i]) {
+                problems.push(format!("{}: no glyph for '{}' in \"{}\"", at, chars[i], word));
+            }
+            px += id.map_or(0, |id| font.glyphs[id].advance);
+            i += len;
+        }
+        if px > ROW_PX {
+            problems.push(format!("{}: \"{}\" is too wide for a row and will be hyphenated", at, word));
+        }
+    }
+}
@@ -1,13 +1,16 @@
-mod world;
+use game::world;
 
+use std::collections::HashMap;
 use std::process::exit;
 use std::rc::Rc;
 
 use engine::animations::AnimationSet;
+use engine::locale::Locale;
 use engine::npc::NPCSet;
 use engine::sprite::{Action, Sprite};
-use engine::text::{Textbox, Textset, Textscreen};
+use engine::text::{Log, Menu, MenuResult, NameEntry, Textbox, Textset, Textscreen};
 use engine::tiles::*;
+use engine::trigger::{Actor, TriggerKind};
 use engine::types::*;
 
 struct Assets {
@@ -15,7 +18,10 @@ struct Assets {
 }
 
 struct State {
-    maps: [Tilemap; 3],
+    /// The level's map and whatever maps joined to it are in view
+    world: ConnectedMaps,
+    /// The next level's map, revealed under the current one while swapping
+    next: Option<Tilemap>,
     level: usize,
 
     talkc: u8,
@@ -26,19 +32,41 @@ struct State {
     spritesheet: Rc<Image>,
 
     npcs: NPCSet,
+    locale: Locale,
+    dlg: HashMap<String, String>,
 
     movec: u8,
+    /// Frames per pixel of the current move
+    pace: u8,
+    /// On ice, moving on its own until it reaches something else
+    sliding: bool,
     cur_dir: usize,
     next_dir: Option<usize>,
 
     is_text: bool,
     textbox: Textbox,
 
+    is_menu: bool,
+    menu: Menu,
+
+    is_speed_menu: bool,
+    speed_menu: Menu,
+
+    is_log: bool,
+    log: Log,
+
+    /// Which of world::NAMES is being entered
+    naming: Option<usize>,
+    name_entry: NameEntry,
+
     textscreen: Textscreen,
     open: bool,
     end: bool,
     wipe_dir: i32,
-    cit: i32
+    cit: i32,
+
+    tick: usize,
+    steps: usize,
 }
 
 impl State {
@@ -46,7 +74,9 @@ impl State {
         let exe_path = std::env::current_exe().unwrap();
         let exe_dir = exe_path.parent().unwrap();
 
-        let maps = [world::map01(), world::map02(), world::map03()];
+        let mut maps = ConnectedMaps::new(world::loader(), world::LEVELS[0].name, world::STREAM_RANGE);
+        maps.stream(START);
+        let next = maps.load(world::LEVELS[1].name);
         let anims = AnimationSet::new(
             exe_dir.join("content/sp01ash.png").as_path(), 
             world::anims(Vec2i { x: 16, y: 16 })
@@ -59,22 +89,60 @@ impl State {
         let spritesheet = Rc::new(Image::from_file(
             exe_dir.join("content/sp01ash.png").as_path()
         ));
-        let npcs = world::npcs01();
+        let locale = world::locale();
+        let npcs = world::npcs01(&locale);
 
-        let textset = Textset::new(
+        let textset = Rc::new(Textset::new(
             exe_dir.join("content/textsheet.png").as_path(),
-            world::text_coords
+            world::font()
+        ));
+        let mut textbox = Textbox::new(textset.clone());
+        textbox.vars.insert("PLAYER".into(), "ASH".into());
+        textbox.vars.insert("RIVAL".into(), "GARY".into());
+        textbox.vars.insert("STEPS".into(), "0".into());
+
+        let items = world::TEXT_SPEEDS.map(|(id, _)| locale.text(world::TEXT, id));
+        let items = items.iter().map(|item| item.as_str()).collect::<Vec<&str>>();
+        let speed_menu = Menu::new(textset.clone(), Vec2i { x: 0, y: 0 }, &items, items.len());
+        let mut name_entry = NameEntry::new(
+            textset.clone(),
+            Vec2i { x: 0, y: 0 },
+            world::NAME_CHARS,
+            [&locale.text(world::TEXT, "NAME_DEL"), &locale.text(world::TEXT, "NAME_END")],
+            world::NAME_LEN
+        );
+        name_entry.window.position = Vec2i {
+            x: (WIDTH as i32 - TILE_SZ * name_entry.window.dims.0 as i32) / 2,
+            y: (HEIGHT as i32 - TILE_SZ * name_entry.window.dims.1 as i32) / 2,
+        };
+        let log = Log::new(
+            textset.clone(),
+            Vec2i { x: 0, y: 0 },
+            (WIDTH / TILE_SZ as usize, HEIGHT / TILE_SZ as usize)
         );
-        let textbox = Textbox::new(Rc::new(textset));
 
-        let textset2 = Textset::new(
+        // YES/NO sits on the right, just above the textbox
+        let mut menu = Menu::new(
+            textset,
+            Vec2i { x: 0, y: 0 },
+            &[&locale.text(world::TEXT, "YES"), &locale.text(world::TEXT, "NO")],
+            2
+        );
+        menu.window.position = Vec2i {
+            x: WIDTH as i32 - TILE_SZ * menu.window.dims.0 as i32,
+            y: textbox.window.position.y - TILE_SZ * menu.window.dims.1 as i32,
+        };
+
+        let mut textset2 = Textset::new(
             exe_dir.join("content/textsheet2.png").as_path(),
-            world::text_coords
+            world::font()
         );
-        let textscreen = Textscreen::new(Rc::new(textset2), &world::open_text());
+        textset2.ink = (255, 255, 255, 255);
+        let textscreen = Textscreen::new(Rc::new(textset2), &world::open_text(&locale));
 
         Self {
-            maps,
+            world: maps,
+            next: Some(next),
             level: 0,
             talkc: 0,
             swapping: false,
@@ -82,16 +150,30 @@ impl State {
             sprite,
             spritesheet,
             npcs,
+            dlg: world::dlg(0, &locale),
+            locale,
             movec: 0,
+            pace: 2,
+            sliding: false,
             cur_dir: DOWN,
             next_dir: None,
             is_text: false,
             textbox,
+            is_menu: false,
+            menu,
+            is_speed_menu: false,
+            speed_menu,
+            is_log: false,
+            log,
+            naming: None,
+            name_entry,
             textscreen,
             open: true,
             end: false,
             wipe_dir: -1,
             cit: -1,
+            tick: 0,
+            steps: 0,
         }
     }
 
@@ -130,8 +212,50 @@ impl State {
             _ => ()
         }
 
+        let map = self.next.take().expect("Swapped without the next level's map");
+        self.world.set_active(world::LEVELS[self.level].name, map);
+        self.world.stream(self.sprite.pos);
+        if let Some(level) = world::LEVELS.get(self.level + 1) {
+            let mut next = self.world.load(level.name);
+            next.position = self.world.active.position;
+            self.next = Some(next);
+        }
+
         self.textbox.set_base(self.level);
-        self.npcs = world::npcs(self.level);
+        self.menu.window.set_theme(self.level);
+        self.speed_menu.window.set_theme(self.level);
+        self.log.window.set_theme(self.level);
+        self.npcs = world::npcs(self.level, &self.locale);
+        self.dlg = world::dlg(self.level, &self.locale);
+    }
+
+    /// Start the move step calls for onto next_pos, false if it can't be taken
+    fn start_move(&mut self, step: Step, next_pos: Vec2i) -> bool {
+        self.pace = 2;
+        self.sliding = false;
+        match step {
+            Step::Jump(land) if self.npcs.at(next_pos).is_none() && self.npcs.at(land).is_none() => {
+                self.sprite.pos = land;
+                self.movec = 64;
+            },
+            Step::Slow if self.npcs.at(next_pos).is_none() => {
+                // the same distance at half the speed
+                self.sprite.pos.walk(self.cur_dir);
+                self.movec = 64;
+                self.pace = 4;
+            },
+            Step::Slide if self.npcs.at(next_pos).is_none() => {
+                self.sprite.pos.walk(self.cur_dir);
+                self.movec = 32;
+                self.sliding = true;
+            },
+            Step::Walk | Step::Grass if self.npcs.at(next_pos).is_none() => {
+                self.sprite.pos.walk(self.cur_dir);
+                self.movec = 32;
+            },
+            _ => return false
+        }
+        true
     }
 
     fn anim(&mut self, act: Action) {
@@ -139,31 +263,39 @@ impl State {
     }
 
     fn translate(&mut self) {
-        for map in self.maps.iter_mut() {
-            map.translate(self.cur_dir);
+        self.world.translate(self.cur_dir);
+        if let Some(next) = self.next.as_mut() {
+            next.translate(self.cur_dir);
         }
     }
 
-    fn circle_mask(&mut self) {
-        let tix = 2 * self.sprite.pos.x as usize;
-        let tiy = 2 * self.sprite.pos.y as usize;
-
-
-        for y in 0..6 {
-            match y {
-                0 | 5 => for x in 0..2 {
-                    self.maps[self.level].mask.unmask(tix + x, tiy - 2 + y);
-                },
-                1 | 4 => for x in 0..4 {
-                    self.maps[self.level].mask.unmask(tix - 1 + x, tiy - 2 + y);
-                },
-                2 | 3 => for x in 0..6 {
-                    self.maps[self.level].mask.unmask(tix - 2 + x, tiy - 2 + y);
-                },
-                _ => panic!("Something went wrong masking the map")
-            }
+    fn tick(&mut self) {
+        self.tick += 1;
+        self.world.set_tick(self.tick);
+        if let Some(next) = self.next.as_mut() {
+            next.set_tick(self.tick);
         }
     }
+
+    /// Walking off the edge of a map onto a joined one makes it the active map
+    fn cross(&mut self) {
+        let pos = self.world.cross(self.sprite.pos);
+        if pos != self.sprite.pos {
+            // NPCs stay where they stood, in the new map's cells
+            self.npcs.shift(pos - self.sprite.pos);
+            self.sprite.pos = pos;
+        }
+        self.world.stream(self.sprite.pos);
+    }
+
+    fn circle_mask(&mut self) {
+        // Centered on the corner between the player's four tiles
+        let center = (
+            2.0 * self.sprite.pos.x as f32 + 1.0,
+            2.0 * self.sprite.pos.y as f32 + 1.0
+        );
+        self.world.active.mask.paint(center, &Brush::Circle(2.75), 0.0);
+    }
 }
 
 struct Game {}
@@ -190,36 +322,40 @@ fn update_state(s: &mut State, now_keys: &[bool], prev_keys: &[bool]) {
     // OPEN TEXT
     #[allow(clippy::collapsible_if)]
     if s.open {
+        s.textscreen.hurry = now_keys[SHIFT];
         if now_keys[SPACE] && !prev_keys[SPACE] {
-            if !s.textscreen.scroll() && s.open {
+            if !s.textscreen.is_revealed() {
+                s.textscreen.finish();
+            } else if !s.textscreen.scroll() && s.open {
                 s.open = false;
                 s.textscreen.animc = WIPENUM - 1;
+                s.naming = Some(0);
+                s.name_entry.set_prompt(&s.locale.text(world::TEXT, world::NAMES[0].1));
             }
         } 
-        if s.textscreen.cptr < 40 * TSPEED {
-            s.textscreen.cptr += 1;
-        }
+        s.textscreen.tick();
         return
     }
 
     // END TEXT
     #[allow(clippy::collapsible_if)]
     if s.end {
+        s.textscreen.hurry = now_keys[SHIFT];
         if now_keys[SPACE] && !prev_keys[SPACE] {
-            if !s.textscreen.scroll() && s.end {
+            if !s.textscreen.is_revealed() {
+                s.textscreen.finish();
+            } else if !s.textscreen.scroll() && s.end {
                 s.cit = 0;
                 return
             }
         } 
-        if s.textscreen.cptr < 40 * TSPEED {
-            s.textscreen.cptr += 1;
-        }
+        s.textscreen.tick();
         return
     }
 
     if s.textscreen.animc == WIPENUM - 1 && s.level == 2 {
         s.end = true;
-        s.textscreen.set_text(&world::end_text());
+        s.textscreen.set_text(&world::end_text(&s.locale));
         s.is_text = true;
     }
 
@@ -227,6 +363,72 @@ fn update_state(s: &mut State, now_keys: &[bool], prev_keys: &[bool]) {
         return
     }
 
+    // NAME ENTRY -> each of world::NAMES in turn, once the open text is gone
+    if let Some(i) = s.naming {
+        if let Some(name) = s.name_entry.update(now_keys, prev_keys) {
+            s.textbox.vars.insert(world::NAMES[i].0.into(), name);
+            s.name_entry.reset();
+            if i + 1 < world::NAMES.len() {
+                s.name_entry.set_prompt(&s.locale.text(world::TEXT, world::NAMES[i + 1].1));
+                s.naming = Some(i + 1);
+            } else {
+                s.naming = None;
+            }
+        }
+        return
+    }
+
+    // YES/NO PROMPT -> move on to the next town or keep looking around
+    if s.is_menu {
+        match s.menu.update(now_keys, prev_keys) {
+            Some(MenuResult::Chosen(0)) => {
+                s.is_menu = false;
+                s.is_text = false;
+                s.swapping = true;
+            },
+            Some(_) => {
+                // asked again after the next talk
+                s.is_menu = false;
+                s.is_text = false;
+                s.npcs.fin = false;
+            },
+            None => ()
+        }
+        return
+    }
+
+    // TEXT SPEED MENU (TAB)
+    if s.is_speed_menu {
+        if let Some(result) = s.speed_menu.update(now_keys, prev_keys) {
+            if let MenuResult::Chosen(i) = result {
+                s.textbox.speed = world::TEXT_SPEEDS[i].1;
+                s.textscreen.speed = world::TEXT_SPEEDS[i].1;
+            }
+            s.is_speed_menu = false;
+        }
+        return
+    }
+    if now_keys[TAB] && !prev_keys[TAB] && !s.is_text && s.movec == 0 && !s.swapping {
+        s.speed_menu.reset();
+        s.speed_menu.cursor = world::TEXT_SPEEDS.iter().position(|(_, speed)| *speed == s.textbox.speed).unwrap_or(0);
+        s.is_speed_menu = true;
+        return
+    }
+
+    // DIALOGUE LOG (L)
+    let log_key = KEY_A + 11;
+    if s.is_log {
+        if s.log.update(now_keys, prev_keys) || (now_keys[log_key] && !prev_keys[log_key]) {
+            s.is_log = false;
+        }
+        return
+    }
+    if now_keys[log_key] && !prev_keys[log_key] && !s.is_text && s.movec == 0 && !s.swapping {
+        s.log.open(&s.textbox.history);
+        s.is_log = true;
+        return
+    }
+
     // RELEASED -> clear next_dir
     if !now_keys[DOWN] && prev_keys[DOWN] && s.next_dir == Some(DOWN) {
         s.next_dir = None;
@@ -264,9 +466,23 @@ fn update_state(s: &mut State, now_keys: &[bool], prev_keys: &[bool]) {
         _ => panic!("Invalid direction")
     };
 
+    // COUNTERS -> talk to whoever is behind them
+    let talk_pos = match s.world.step(s.sprite.pos, s.next_dir.unwrap_or(s.cur_dir)) {
+        Step::TalkOver(behind) => behind,
+        _ => next_pos
+    };
+
     // MOVEMENT DONE
     if s.movec == 0 {
-        if s.next_dir == None { // NO HELD KEY
+        if s.sliding {
+            // ICE -> keep going the same way until off the ice or stopped
+            if !s.is_text {
+                let mut ahead = s.sprite.pos;
+                ahead.walk(s.cur_dir);
+                let step = s.world.step(s.sprite.pos, s.cur_dir);
+                s.start_move(step, ahead);
+            }
+        } else if s.next_dir == None { // NO HELD KEY
             // stand in current direction
             match s.cur_dir {
                 DOWN => s.anim(Action::StandD),
@@ -289,72 +505,100 @@ fn update_state(s: &mut State, now_keys: &[bool], prev_keys: &[bool]) {
                 }
             };
     
-            if !s.is_text && (
-               (s.maps[s.level].can_move_to(next_pos) && 
-               matches!(s.npcs.at(next_pos), None)) ||
-               ((Tilemap::swap_can_move_to(next_pos)) && 
-               (s.swapping || !s.maps[s.level].can_move_to(s.sprite.pos))))
-            {
-                s.sprite.pos.walk(s.cur_dir);
-                s.movec = 32;
+            if !s.is_text {
+                let swap_move = world::SWAP_AREA.contains_point(next_pos) && 
+                    (s.swapping || !s.world.active.can_move_to(s.sprite.pos));
+
+                let step = s.world.step(s.sprite.pos, s.cur_dir);
+                if !s.start_move(step, next_pos) && swap_move {
+                    s.sprite.pos.walk(s.cur_dir);
+                    s.movec = 32;
+                }
             }
 
         }
 
         // INTERACT KEY (SPACE)
         if now_keys[SPACE] && !prev_keys[SPACE] && !s.swapping {
-            if let Some(npc) = s.npcs.at(next_pos) {
-                if s.is_text {
-                    let more = s.textbox.scroll();
-                    if !more {
-                        if s.npcs.fin {
-                            s.is_text = false;
-                            if s.level == 2 {
-                                s.textscreen.animc = 1;
-                                s.wipe_dir = 1;
-                                return
-                            } else {
-                                s.swapping = true;
-                            }
-                        } else if s.talkc >= 4 {
-                            s.textbox.set_text(&s.npcs.fin_text);
-                            s.npcs.fin = true;
+            if s.is_text && !s.textbox.is_revealed() {
+                // SKIP TO THE END OF THE PAGE
+                s.textbox.finish();
+            } else if s.is_text && s.npcs.fin && s.level < 2 && s.textbox.is_last() {
+                // ASK BEFORE LEAVING
+                s.menu.reset();
+                s.is_menu = true;
+            } else if s.is_text {
+                let more = s.textbox.scroll();
+                if !more {
+                    if s.npcs.fin {
+                        s.is_text = false;
+                        if s.level == 2 {
+                            s.textscreen.animc = 1;
+                            s.wipe_dir = 1;
+                            return
                         } else {
-                            s.is_text = false;
+                            s.swapping = true;
                         }
+                    } else if s.talkc >= 4 {
+                        s.textbox.speaker = None;
+                        s.textbox.set_text(&s.npcs.fin_text);
+                        s.npcs.fin = true;
+                    } else {
+                        s.is_text = false;
                     }
-                } else {
-                    npc.turn_to_face(s.cur_dir);
-                    s.textbox.set_text(&npc.text);
-                    s.is_text = !s.is_text;
-                    if npc.id < 4 && !npc.talked {
-                        s.talkc += 1;
-                        npc.talked = true;
-                    }
                 }
+            } else if let Some(npc) = s.npcs.at(talk_pos) {
+                npc.turn_to_face(s.cur_dir);
+                s.textbox.speaker = Some(npc.name.clone());
+                s.textbox.set_text(&npc.text);
+                s.is_text = true;
+                if !npc.talked {
+                    s.talkc += 1;
+                    npc.talked = true;
+                }
+            } else if let Some(Interaction::Text(key)) = s.world.active.interaction_at(talk_pos) {
+                // SIGNS AND OTHER OBJECTS
+                s.textbox.speaker = None;
+                s.textbox.set_text(&s.dlg[key]);
+                s.is_text = true;
             }
         }
     }
 
-    // TEXT REVEAL
-    if s.textbox.cptr < 40 * TSPEED {
-        s.textbox.cptr += 1;
-    }
+    // TEXT REVEAL -> hold SHIFT to hurry it
+    s.textbox.hurry = now_keys[SHIFT];
+    s.textbox.tick();
 
     // HANDLE MOVEMENT
     if s.movec > 0 {
         s.movec -= 1;
 
-        if s.movec % 2 == 1 {
+        if s.movec % s.pace == 1 {
             s.translate();
             if s.swapping {
                 s.circle_mask();
             }
         }
+
+        // STEP DONE -> count it and switch maps when across a seam
+        if s.movec == 0 && !s.swapping {
+            s.cross();
+            s.steps += 1;
+            s.textbox.vars.insert("STEPS".into(), s.steps.to_string());
+
+            // TRIGGERS -> zones with dialogue speak up when entered
+            for event in s.world.active.triggers.update(Actor::Player, s.sprite.pos) {
+                if let (TriggerKind::Enter, Some(text)) = (event.kind, s.dlg.get(&event.zone)) {
+                    s.textbox.speaker = None;
+                    s.textbox.set_text(text);
+                    s.is_text = true;
+                }
+            }
+        }
     }
 
     // COMPLETE SWAP
-    if s.swapping && s.maps[s.level].mask.swapc >= SWAPNUM {
+    if s.swapping && s.world.active.mask.swapc >= SWAPNUM {
         s.next_level();
     }
 }
@@ -390,6 +634,7 @@ impl engine::eng::Game for Game {
     }
 
     fn update(s: &mut State, _assets: &mut Assets, now_keys: &[bool], prev_keys: &[bool]) {
+        s.tick();
         update_state(s, now_keys, prev_keys);
     }
 
@@ -411,18 +656,36 @@ impl engine::eng::Game for Game {
             return
         }
 
-        if s.swapping {
-            s.maps[s.level+1].draw(fb2d);
-            s.maps[s.level].masked_draw(fb2d);
+        if let (true, Some(next)) = (s.swapping, s.next.as_ref()) {
+            composite(next, &s.world.active, fb2d);
         } else {
-            s.maps[s.level].draw(fb2d);
-            s.npcs.draw(fb2d, s.sprite.pos, s.movec, s.cur_dir);
+            s.world.draw(fb2d);
+            // NPCs are offset by movec / 2 pixels, slow moves cover fewer pixels per frame
+            s.npcs.draw(fb2d, s.sprite.pos, s.movec * 2 / s.pace, s.cur_dir);
         }
         render_player(s, assets, fb2d);
 
+        if let (true, Some(next)) = (s.swapping, s.next.as_ref()) {
+            composite_above(next, &s.world.active, fb2d);
+        } else {
+            s.world.draw_above(fb2d);
+        }
+
         if s.is_text {
             s.textbox.draw(fb2d);
         }
+        if s.is_menu {
+            s.menu.draw(fb2d);
+        }
+        if s.is_speed_menu {
+            s.speed_menu.draw(fb2d);
+        }
+        if s.is_log {
+            s.log.draw(fb2d);
+        }
+        if s.naming.is_some() && s.textscreen.animc == 0 {
+            s.name_entry.draw(fb2d);
+        }
 
         if s.textscreen.animc < WIPENUM && s.textscreen.animc > 0 {
             s.textscreen.anim(fb2d);
@@ -1,20 +1,73 @@
 use engine::animations::{Animation};
+use engine::locale::Locale;
 use engine::npc::{NPC, NPCSet};
 use engine::sprite::Action;
+use engine::text::{Font, TextSpeed};
 use engine::tiles::*;
 use engine::types::*;
 
-use std::fs::read_to_string;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-pub fn open_text() -> String {
-    "The original POKéMON game was 512 kilobytes. That's a fraction of the size of the average photo on your phone. But those 512 kilobytes were the start of the highest grossing media franchise of all time.".into()
+/// Movement cells the player can roam while swapping eras, even over solid tiles
+pub const SWAP_AREA: Rect = Rect {
+    pos: Vec2i { x: 5, y: 6 },
+    sz: Vec2i { x: 17, y: 15 },
+};
+
+/// Locales live in content/locale/<name>, each a directory of string tables
+pub const LOCALES: &str = "locale";
+/// Locale that fills in for anything another locale leaves out
+pub const DEFAULT_LOCALE: &str = "en";
+/// String table for text that isn't a level's dialogue
+pub const TEXT: &str = "text";
+/// String table of what NPCs are called in the dialogue history, by dialogue key
+pub const SPEAKERS: &str = "names";
+
+/// Locale from `--locale <name>`, or else the system language if there's a locale for it
+pub fn locale_name(root: &Path) -> String {
+    let mut args = std::env::args().skip_while(|a| a != "--locale");
+    if let Some(name) = args.nth(1) {
+        return name;
+    }
+    let lang = std::env::var("LANG").unwrap_or_default();
+    let lang = lang.split(['_', '.']).next().unwrap_or_default();
+    if !lang.is_empty() && root.join(lang).is_dir() {
+        return lang.to_string();
+    }
+    DEFAULT_LOCALE.to_string()
+}
+
+/// Choices in the text speed menu, by ID in the text table
+pub const TEXT_SPEEDS: [(&str, TextSpeed); 4] = [
+    ("SLOW", TextSpeed::Slow),
+    ("MEDIUM", TextSpeed::Medium),
+    ("FAST", TextSpeed::Fast),
+    ("INSTANT", TextSpeed::Instant),
+];
+
+/// Dialogue variables the player names before setting off, with the ID in TEXT of what asks for each
+pub const NAMES: [(&str, &str); 2] = [
+    ("PLAYER", "NAME_PLAYER"),
+    ("RIVAL", "NAME_RIVAL"),
+];
+/// Characters names are picked from
+pub const NAME_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+/// Longest name, short enough for a textbox row next to any word
+pub const NAME_LEN: usize = 7;
+
+pub fn locale() -> Locale {
+    let root = content_dir().join(LOCALES);
+    Locale::load(root.as_path(), &locale_name(root.as_path()), DEFAULT_LOCALE)
 }
 
-pub fn end_text() -> String {
-    "A cable, Godzilla, and bugs were the inspirations for an idea. Thousands of people developed that idea into a media empire. It wasn't by chance. If it ain't broke, don't fix it. That's what they say, right? The core gameplay of POKéMON is no different than it was in 1996. The idea was just so great that it holds up 25 years later. And the expansions of the franchise, the anime, the movies, the cards, the spinoffs, the mobile game, were well thought out extensions, not just quick and cheap money grabs. Sure, it was driven by money. Everything is. But alongside the obsession to make the content of this franchise profitable was a persistent obsession with making it well. After all, this did all start with a man and his desire to distill the joy of his childhood into something everyone could enjoy.".into()
+pub fn open_text(locale: &Locale) -> String {
+    locale.text(TEXT, "OPEN")
+}
+
+pub fn end_text(locale: &Locale) -> String {
+    locale.text(TEXT, "END")
 }
 
 pub fn anims(sz: Vec2i) -> HashMap<Action, Rc<Animation>> {
@@ -105,226 +158,207 @@ pub fn anims(sz: Vec2i) -> HashMap<Action, Rc<Animation>> {
     animations
 }
 
-pub fn text_coords(c: usize) -> Vec2i {
-    let coords = if (65..=90).contains(&c) {
-        Vec2i { x: (c - 65) as i32, y: 0 }
-    } else if (97..=122).contains(&c) {
-        Vec2i { x: (c - 97) as i32, y: 1 }
-    } else if (48..=57).contains(&c) {
-        Vec2i { x: (c - 48) as i32, y: 2 }
-    } else {
-        match c {
-            1 => Vec2i { x: 0, y: 3 }, // top left
-            2 => Vec2i { x: 2, y: 3 }, // top right
-            3 => Vec2i { x: 5, y: 3 }, // bottom right
-            4 => Vec2i { x: 4, y: 3 }, // bottom left
-            5 => Vec2i { x: 1, y: 3 }, // horizontal bar
-            6 => Vec2i { x: 1, y: 3 }, // horizontal bar
-            7 => Vec2i { x: 3, y: 3 }, // vertical bar
-            8 => Vec2i { x: 3, y: 3 }, // vertical bar
-            9 => Vec2i { x: 21, y: 3 }, // down arrow
-            11 => Vec2i { x: 0, y: 4 }, // top left
-            12 => Vec2i { x: 2, y: 4 }, // top right
-            13 => Vec2i { x: 4, y: 4 }, // bottom right
-            14 => Vec2i { x: 6, y: 4 }, // bottom left
-            15 => Vec2i { x: 1, y: 4 }, // horizontal bar
-            16 => Vec2i { x: 1, y: 4 }, // horizontal bar
-            17 => Vec2i { x: 7, y: 4 }, // vertical bar left
-            18 => Vec2i { x: 3, y: 4 }, // vertical bar right
-            19 => Vec2i { x: 21, y: 3 }, // down arrow
-            21 => Vec2i { x: 8, y: 4 }, // top left
-            22 => Vec2i { x: 10, y: 4 }, // top right
-            23 => Vec2i { x: 12, y: 4 }, // bottom right
-            24 => Vec2i { x: 14, y: 4 }, // bottom left
-            25 => Vec2i { x: 9, y: 4 }, // horizontal bar top
-            26 => Vec2i { x: 13, y: 4 }, // horizontal bar bottom
-            27 => Vec2i { x: 15, y: 4 }, // vertical bar left
-            28 => Vec2i { x: 11, y: 4 }, // vertical bar right
-            29 => Vec2i { x: 21, y: 3 }, // down arrow
-            233 => Vec2i { x: 10, y: 2 }, // é
-            256 => Vec2i { x: 11, y: 2 }, // 'd
-            257 => Vec2i { x: 12, y: 2 }, // 'l
-            258 => Vec2i { x: 13, y: 2 }, // 's
-            259 => Vec2i { x: 14, y: 2 }, // 't
-            260 => Vec2i { x: 15, y: 2 }, // 'v
-            261 => Vec2i { x: 16, y: 2 }, // 'r
-            262 => Vec2i { x: 17, y: 2 }, // 'm
-            263 => Vec2i { x: 18, y: 3 }, // " open
-            264 => Vec2i { x: 19, y: 3 }, // " close
-            40 => Vec2i { x: 6, y: 3 }, // (
-            41 => Vec2i { x: 7, y: 3 }, // )
-            58 => Vec2i { x: 8, y: 3 }, // :
-            59 => Vec2i { x: 9, y: 3 }, // ;
-            91 => Vec2i { x: 10, y: 3 }, // [
-            93 => Vec2i { x: 11, y: 3 }, // ]
-            45 => Vec2i { x: 12, y: 3 }, // -
-            63 => Vec2i { x: 13, y: 3 }, // ?
-            33 => Vec2i { x: 14, y: 3 }, // !
-            46 => Vec2i { x: 15, y: 3 }, // .
-            47 => Vec2i { x: 16, y: 3 }, // /
-            44 => Vec2i { x: 17, y: 3 }, // ,
-            42 => Vec2i { x: 20, y: 3 }, // * -> ...
-            _ => Vec2i { x: 22, y: 3 } // space (default)
-        }
-    };
-    Vec2i { x: TILE_SZ * coords.x, y: TILE_SZ * coords.y } // space (default)
-}
-
-pub fn npcs(level: usize) -> NPCSet {
+pub fn npcs(level: usize, locale: &Locale) -> NPCSet {
     match level {
-        0 => npcs01(),
-        1 => npcs02(),
-        2 => npcs03(),
+        0 => npcs01(locale),
+        1 => npcs02(locale),
+        2 => npcs03(locale),
         _ => panic!("Invalid level")
     }
 }
 
-pub fn map01() -> Tilemap {
-    let exe_path = std::env::current_exe().unwrap();
-    let exe_dir = exe_path.parent().unwrap();
-
-    let tilesheet = Rc::new(Image::from_file(
-        exe_dir.join("content/ts01.png").as_path(),
-    ));
-    let solid = (0..96)
-        .map(|x| Tile { solid: !(x == 0 || x == 3 || x == 44 || x == 57) })
-        .collect::<Vec<Tile>>();
-    let tileset = Rc::new(Tileset::new(
-        solid,
-        tilesheet,
-    ));
-    Tilemap::from_csv(
-        Vec2i { x: PPOS.x - MOVE_SZ * START.x, y: PPOS.y - MOVE_SZ * START.y },
-        (56, 54),
-        tileset,
-        exe_dir.join("content/tm01.csv").as_path(),
-        2,
-        vec![0, 3, 44, 57],
-    )
+/// How close to a map's edge, in movement cells, before the map joined there is loaded.
+/// The screen shows 5 cells either side of the player.
+pub const STREAM_RANGE: i32 = 6;
+
+pub fn loader() -> MapLoader {
+    let mut loader = MapLoader::new();
+    loader.add(LEVELS[0].name, map01);
+    loader.add(LEVELS[1].name, map02);
+    loader.add(LEVELS[2].name, map03);
+    loader
 }
 
-pub fn npcs01() -> NPCSet {
-    let exe_path = std::env::current_exe().unwrap();
-    let exe_dir = exe_path.parent().unwrap();
-
-    let raw = read_to_string(exe_dir.join("content/dlg01.json").as_path()).unwrap();
-    let dlg: HashMap<String, String> = serde_json::from_str::<HashMap<String, String>>(&raw).unwrap();
-
-    let npcs = vec![
-        NPC::new(0, 0, Vec2i { x: 15, y: 18 }, dlg["BOY"].to_string()),
-        NPC::new(1, 2, Vec2i { x: 7,  y: 13 }, dlg["WOMAN"].to_string()),
-        NPC::new(2, 3, Vec2i { x: 20, y: 15 }, dlg["OAK"].to_string()),
-        NPC::new(3, 0, Vec2i { x: 10, y: 10 }, dlg["MOM"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 7,  y: 9  }, dlg["HSIGN"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 15, y: 9  }, dlg["RSIGN"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 11, y: 13 }, dlg["TSIGN"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 17, y: 17 }, dlg["BSIGN"].to_string()),
-    ];
+/// Size of every level map in tiles
+pub const MAP_DIMS: (usize, usize) = (56, 54);
 
-    NPCSet::new(
-        exe_dir.join("content/npcs01.png").as_path(),
-        npcs,
-        Vec2i { x: 16, y: 16 },
-        String::from("Looks like you've talked to everyone here in PALLET TOWN! Why don't you walk around a bit?")
-    )
+/// Content files and signs for one level, relative to the content directory.
+/// NPCs are placed in <map>.npcs.json, written by the map editor.
+pub struct Level {
+    /// Name of the level's map in the loader
+    pub name: &'static str,
+    pub tilesheet: &'static str,
+    pub tiles: &'static str,
+    pub map: &'static str,
+    /// String table with the level's dialogue
+    pub dlg: &'static str,
+    pub npc_sheet: &'static str,
+    pub npc_sz: Vec2i,
+    /// (cell, dialogue key) of solid objects to read
+    pub signs: &'static [(Vec2i, &'static str)],
+    /// ID in TEXT of what's said once everyone has been talked to
+    pub fin: &'static str,
 }
 
-pub fn map02() -> Tilemap {
+pub const LEVELS: [Level; 3] = [
+    Level {
+        name: "PALLET",
+        tilesheet: "ts01.png",
+        tiles: "ts01.json",
+        map: "tm01.csv",
+        dlg: "dlg01",
+        npc_sheet: "npcs01.png",
+        npc_sz: Vec2i { x: 16, y: 16 },
+        signs: &[
+            (Vec2i { x: 7,  y: 9  }, "HSIGN"),
+            (Vec2i { x: 15, y: 9  }, "RSIGN"),
+            (Vec2i { x: 11, y: 13 }, "TSIGN"),
+            (Vec2i { x: 17, y: 17 }, "BSIGN"),
+        ],
+        fin: "PALLET_FIN",
+    },
+    Level {
+        name: "NEWBARK",
+        tilesheet: "ts02.png",
+        tiles: "ts02.json",
+        map: "tm02.csv",
+        dlg: "dlg02",
+        npc_sheet: "npcs02.png",
+        npc_sz: Vec2i { x: 16, y: 16 },
+        signs: &[
+            (Vec2i { x: 7,  y: 8  }, "LSIGN"),
+            (Vec2i { x: 15, y: 10 }, "HSIGN"),
+            (Vec2i { x: 12, y: 13 }, "MSIGN"),
+            (Vec2i { x: 13, y: 18 }, "BSIGN"),
+        ],
+        fin: "NEWBARK_FIN",
+    },
+    Level {
+        name: "LITTLEROOT",
+        tilesheet: "ts03.png",
+        tiles: "ts03.json",
+        map: "tm03.csv",
+        dlg: "dlg03",
+        npc_sheet: "npcs03.png",
+        npc_sz: Vec2i { x: 16, y: 20 },
+        signs: &[
+            (Vec2i { x: 14, y: 19 }, "LSIGN"),
+            (Vec2i { x: 12, y: 11 }, "HSIGN"),
+            (Vec2i { x: 18, y: 15 }, "MSIGN"),
+            (Vec2i { x: 16, y: 11 }, "FSIGN"),
+        ],
+        fin: "LITTLEROOT_FIN",
+    },
+];
+
+/// The content directory shipped next to the executable
+pub fn content_dir() -> PathBuf {
     let exe_path = std::env::current_exe().unwrap();
-    let exe_dir = exe_path.parent().unwrap();
-
-    let tilesheet = Rc::new(Image::from_file(std::path::Path::new(
-        exe_dir.join("content/ts02.png").as_path(),
-    )));
-    let solid = (0..96)
-        .map(|x| Tile { solid: !(x == 0 || x == 3 || x == 5 || x == 6) })
-        .collect::<Vec<Tile>>();
-    let tileset = Rc::new(Tileset::new(
-        solid,
-        tilesheet,
-    ));
-    Tilemap::from_csv(
-        Vec2i { x: PPOS.x - MOVE_SZ * START.x, y: PPOS.y - MOVE_SZ * START.y },
-        (56, 54),
-        tileset,
-        exe_dir.join("content/tm02.csv").as_path(),
-        2,
-        vec![0, 3, 5, 6],
-    )
+    exe_path.parent().unwrap().join("content")
 }
 
-pub fn npcs02() -> NPCSet {
-    let exe_path = std::env::current_exe().unwrap();
-    let exe_dir = exe_path.parent().unwrap();
-
-    let raw = read_to_string(exe_dir.join("content/dlg02.json").as_path()).unwrap();
-    let dlg: HashMap<String, String> = serde_json::from_str::<HashMap<String, String>>(&raw).unwrap();
-
-    let npcs = vec![
-        NPC::new(0, 0, Vec2i { x: 16, y: 14 }, dlg["BOY"].to_string()),
-        NPC::new(1, 3, Vec2i { x: 7,  y: 7  }, dlg["RIVAL"].to_string()),
-        NPC::new(2, 3, Vec2i { x: 21, y: 12 }, dlg["ELM"].to_string()),
-        NPC::new(3, 1, Vec2i { x: 10, y: 13 }, dlg["WOMAN"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 7,  y: 8  }, dlg["LSIGN"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 15, y: 10 }, dlg["HSIGN"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 12, y: 13 }, dlg["MSIGN"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 13, y: 18 }, dlg["BSIGN"].to_string()),
-    ];
+/// Glyph layout shared by both text sheets
+pub const FONT: &str = "font.json";
 
-    NPCSet::new(
-        exe_dir.join("content/npcs02.png").as_path(),
-        npcs,
-        Vec2i { x: 16, y: 16 },
-        String::from("Oh, you've met everyone in NEW BARK TOWN. Walk around some more and I think you'll find new people to talk to!")
-    )
+pub fn font() -> Font {
+    Font::from_json(content_dir().join(FONT).as_path())
 }
 
-pub fn map03() -> Tilemap {
-    let exe_path = std::env::current_exe().unwrap();
-    let exe_dir = exe_path.parent().unwrap();
-
-    let tilesheet = Rc::new(Image::from_file(std::path::Path::new(
-        exe_dir.join("content/ts03.png").as_path(),
-    )));
-    let solid = (0..96)
-        .map(|x| Tile { solid: x != 0 })
-        .collect::<Vec<Tile>>();
-    let tileset = Rc::new(Tileset::new(
-        solid,
-        tilesheet,
-    ));
-    Tilemap::from_csv(
+/// Dialogue for a level, keyed by the names NPCs and signs use
+pub fn dlg(level: usize, locale: &Locale) -> HashMap<String, String> {
+    locale.table(LEVELS[level].dlg)
+}
+
+pub fn load_map(level: &Level, content: &Path) -> Tilemap {
+    let tilesheet = Rc::new(Image::from_file(content.join(level.tilesheet).as_path()));
+    let mut tileset = Tileset::from_json(tilesheet, content.join(level.tiles).as_path());
+    let anims = content.join(level.tiles).with_extension("anims.json");
+    if anims.exists() {
+        tileset.load_animations(anims.as_path());
+    }
+    let mut map = Tilemap::from_csv(
         Vec2i { x: PPOS.x - MOVE_SZ * START.x, y: PPOS.y - MOVE_SZ * START.y },
-        (56, 54),
-        tileset,
-        exe_dir.join("content/tm03.csv").as_path(),
+        MAP_DIMS,
+        Rc::new(tileset),
+        content.join(level.map).as_path(),
         2,
-        vec![0, 1, 2, 3, 34, 35, 36, 37, 70, 71, 72, 73, 74, 75, 104, 105, 106, 107, 108, 109],
-    )
+    );
+    for &(pos, key) in level.signs {
+        map.add_object(MapObject { pos, interaction: Interaction::Text(key.to_string()), solid: true });
+    }
+    let zones = content.join(level.map).with_extension("zones.json");
+    if zones.exists() {
+        map.load_triggers(zones.as_path());
+    }
+    let connections = content.join(level.map).with_extension("connections.json");
+    if connections.exists() {
+        map.load_connections(connections.as_path());
+    }
+    map.enable_cache(16);
+    map
 }
 
-pub fn npcs03() -> NPCSet {
-    let exe_path = std::env::current_exe().unwrap();
-    let exe_dir = exe_path.parent().unwrap();
-
-    let raw = read_to_string(exe_dir.join("content/dlg03.json").as_path()).unwrap();
-    let dlg: HashMap<String, String> = serde_json::from_str::<HashMap<String, String>>(&raw).unwrap();
-
-    let npcs = vec![
-        NPC::new(0, 3, Vec2i { x: 16, y: 16 }, dlg["BOYA"].to_string()),
-        NPC::new(1, 0, Vec2i { x: 15,  y: 6  }, dlg["GIRL"].to_string()),
-        NPC::new(2, 2, Vec2i { x: 6, y: 14 }, dlg["BIRCH"].to_string()),
-        NPC::new(3, 2, Vec2i { x: 18, y: 18 }, dlg["BOYB"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 14,  y: 19 }, dlg["LSIGN"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 12, y: 11 }, dlg["HSIGN"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 18, y: 15 }, dlg["MSIGN"].to_string()),
-        NPC::new(4, 0, Vec2i { x: 16, y: 11 }, dlg["FSIGN"].to_string()),
-    ];
+/// (sprite row, facing, cell, dialogue key) of the level's NPCs, read from the editor's <map>.npcs.json
+pub fn npc_defs(level: &Level, content: &Path) -> Result<Vec<(i32, i32, Vec2i, String)>, String> {
+    let path = content.join(level.map).with_extension("npcs.json");
+    let file = path.file_name().unwrap().to_string_lossy().to_string();
+    let raw = std::fs::read_to_string(&path).map_err(|error| format!("{}: {}", file, error))?;
+    let defs: serde_json::Value = serde_json::from_str(&raw).map_err(|error| format!("{}: {}", file, error))?;
+
+    defs.as_array()
+        .ok_or(format!("{}: NPCs must be a list", file))?
+        .iter()
+        .enumerate()
+        .map(|(i, def)| {
+            let at = format!("{} NPC {}", file, i + 1);
+            let dir = parse_dir(&def["dir"]).ok_or(format!("{}: invalid dir {}", at, def["dir"]))?;
+            let pos = parse_pos(&def["pos"]).ok_or(format!("{}: invalid pos {}", at, def["pos"]))?;
+            let key = def["key"].as_str().ok_or(format!("{}: needs a dialogue key", at))?;
+            let id = def["id"].as_i64().ok_or(format!("{}: invalid sprite id {}", at, def["id"]))?;
+            Ok((id as i32, dir as i32, pos, key.to_string()))
+        })
+        .collect()
+}
+
+pub fn load_npcs(level: &Level, content: &Path, locale: &Locale) -> NPCSet {
+    let dlg = locale.table(level.dlg);
+    let npcs = npc_defs(level, content)
+        .unwrap_or_else(|error| panic!("{}", error))
+        .into_iter()
+        .map(|(id, dir, pos, key)| {
+            let text = dlg.get(&key).cloned().unwrap_or_else(|| key.clone());
+            NPC::new(id, dir, pos, locale.text(SPEAKERS, &key), text)
+        })
+        .collect();
 
     NPCSet::new(
-        exe_dir.join("content/npcs03.png").as_path(),
+        content.join(level.npc_sheet).as_path(),
         npcs,
-        Vec2i { x: 16, y: 20 },
-        String::from("Well, that's everyone. Hope you enjoyed your time here in LITTLEROOT! Until next time*")
+        level.npc_sz,
+        locale.text(TEXT, level.fin)
     )
-}
\ No newline at end of file
+}
+
+pub fn map01() -> Tilemap {
+    load_map(&LEVELS[0], &content_dir())
+}
+
+pub fn npcs01(locale: &Locale) -> NPCSet {
+    load_npcs(&LEVELS[0], &content_dir(), locale)
+}
+
+pub fn map02() -> Tilemap {
+    load_map(&LEVELS[1], &content_dir())
+}
+
+pub fn npcs02(locale: &Locale) -> NPCSet {
+    load_npcs(&LEVELS[1], &content_dir(), locale)
+}
+
+pub fn map03() -> Tilemap {
+    load_map(&LEVELS[2], &content_dir())
+}
+
+pub fn npcs03(locale: &Locale) -> NPCSet {
+    load_npcs(&LEVELS[2], &content_dir(), locale)
+}